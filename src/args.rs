@@ -1,21 +1,156 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use anyhow::{Context, Result};
-use clap::Parser;
-
-use crate::utils::Link;
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use rumtopf::{Lint, MessageFormat, Site, SiteBuilder};
 
 /// A generator for a static recipe website.
 ///
 /// The exit code is 0 if successful, 1 if a fatal error occurred, and 2 if
 /// generation finished with errors.
 #[derive(Parser)]
-#[command(version)]
+#[command(
+    version,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 pub(crate) struct Args {
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+    /// Directory with recipes in Markdown format.
+    #[arg(required = true)]
+    pub(crate) source: Option<PathBuf>,
+    /// Directory to write generated website to.
+    ///
+    /// Required unless set in the configuration file.
+    pub(crate) destination: Option<PathBuf>,
+    /// Remove entire destination directory before generating the website.
+    ///
+    /// This option removes all contents of the destination. Use with caution.
+    #[arg(short, long)]
+    pub(crate) remove: bool,
+    /// Only regenerate files whose inputs changed since the last generation.
+    ///
+    /// The destination may contain the website of a previous generation.
+    /// Files whose sources were removed are deleted from it.
+    #[arg(short, long, conflicts_with = "remove")]
+    pub(crate) incremental: bool,
+    #[command(flatten)]
+    pub(crate) options: Options,
+}
+
+#[derive(Subcommand)]
+pub(crate) enum Command {
+    /// Serve the website locally and regenerate it when sources change.
+    ///
+    /// Pages are reloaded automatically in the browser after regenerating.
+    Serve(ServeArgs),
+    /// Check the recipes for problems without writing the website.
+    ///
+    /// All pages are rendered in memory to also find problems with templates.
+    /// The exit code is the same as for generating the website.
+    Check(CheckArgs),
+    /// Create a new recipe from a skeleton.
+    ///
+    /// The file name is derived from the title and printed after creating the
+    /// file.
+    New(NewArgs),
+    /// Export the recipes into a single document.
+    ///
+    /// All recipes are included unless selected by language or tag.
+    /// Quantities are shown for the base servings.
+    Export(ExportArgs),
+}
+
+#[derive(clap::Args)]
+pub(crate) struct ServeArgs {
     /// Directory with recipes in Markdown format.
     pub(crate) source: PathBuf,
     /// Directory to write generated website to.
-    pub(crate) destination: PathBuf,
+    ///
+    /// It may contain a previous generation, from which files whose sources
+    /// were removed are deleted.
+    /// Defaults to a new temporary directory, which is removed on exit.
+    pub(crate) destination: Option<PathBuf>,
+    /// Port to listen on at localhost.
+    #[arg(long, default_value_t = 8000)]
+    pub(crate) port: u16,
+    #[command(flatten)]
+    pub(crate) options: Options,
+}
+
+#[derive(clap::Args)]
+pub(crate) struct CheckArgs {
+    /// Directory with recipes in Markdown format.
+    pub(crate) source: PathBuf,
+    #[command(flatten)]
+    pub(crate) options: Options,
+}
+
+#[derive(clap::Args)]
+pub(crate) struct NewArgs {
+    /// Title of the recipe.
+    pub(crate) title: String,
+    /// Directory to create the recipe in.
+    #[arg(short, long, default_value = ".")]
+    pub(crate) source: PathBuf,
+    /// Language of the recipe, which is added to the file name.
+    #[arg(short = 'g', long)]
+    pub(crate) lang: Option<String>,
+    /// Handlebars template of the new recipe to override the built-in one.
+    ///
+    /// `{{title}}` is replaced by the title and `{{l10n "key"}}` by localized
+    /// texts.
+    /// Markup of recipes like `{{2 servings}}` has to be escaped as
+    /// `\{{2 servings}}`.
+    #[arg(short = 'k', long)]
+    pub(crate) skeleton: Option<PathBuf>,
+    /// Custom localization file to override the built-in one.
+    #[arg(short = 'n', long)]
+    pub(crate) l10n: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+pub(crate) struct ExportArgs {
+    /// Directory with recipes in Markdown format.
+    pub(crate) source: PathBuf,
+    /// File to write the document to.
+    #[arg(short, long)]
+    pub(crate) output: PathBuf,
+    /// Format of the document.
+    #[arg(long, value_enum, default_value_t)]
+    pub(crate) format: ExportFormat,
+    /// Only export recipes in this language.
+    ///
+    /// Recipes without a language are in the language given by --lang.
+    #[arg(long, value_name = "LANG")]
+    pub(crate) only_lang: Option<String>,
+    /// Only export recipes with this tag.
+    ///
+    /// Can be specified multiple times to export recipes with any of the tags.
+    #[arg(long, value_name = "TAG")]
+    pub(crate) only_tag: Vec<String>,
+    #[command(flatten)]
+    pub(crate) options: Options,
+}
+
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub(crate) enum ExportFormat {
+    /// Self-contained HTML document for printing with a table of contents.
+    #[default]
+    HtmlBook,
+    /// EPUB 3 e-book with one chapter per recipe of one language.
+    Epub,
+}
+
+/// Options shared by all ways of generating the website.
+#[derive(clap::Args, Clone)]
+pub(crate) struct Options {
+    /// Configuration file with defaults for the options.
+    ///
+    /// Defaults to rumtopf.toml in the source directory if it exists.
+    #[arg(short, long)]
+    pub(crate) config: Option<PathBuf>,
     /// Add custom website title.
     #[arg(short, long)]
     pub(crate) title: Option<String>,
@@ -25,33 +160,109 @@ pub(crate) struct Args {
     /// text and href being the link.
     /// Can be specified multiple times.
     #[arg(short, long, value_parser=parse_link, value_name="LABEL>=<HREF")]
-    pub(crate) link: Vec<Link>,
+    pub(crate) link: Vec<(String, String)>,
     /// Add plain text to footer.
     #[arg(short, long)]
     pub(crate) footer: Option<String>,
     #[arg(short('m'), long)]
     /// Directory with HTML templates to override built-in ones.
     pub(crate) templates: Option<PathBuf>,
-    /// Remove entire destination directory before generating the website.
-    ///
-    /// This option removes all contents of the destination. Use with caution.
-    #[arg(short, long)]
-    pub(crate) remove: bool,
     /// Set language for language selection index and uncategorized recipes.
     #[arg(short = 'g', long, default_value = "en")]
     pub(crate) lang: String,
     /// Custom localization file to override the built-in one.
     #[arg(short = 'n', long)]
     pub(crate) l10n: Option<PathBuf>,
+    /// Pre-render recipe pages for these numbers of servings.
+    ///
+    /// This allows adjusting the servings without JavaScript.
+    /// Values are separated by commas.
+    #[arg(
+        short,
+        long,
+        value_delimiter = ',',
+        value_name = "SERVINGS",
+        value_parser = parse_servings
+    )]
+    pub(crate) prerender: Vec<f32>,
+    /// URL of the website, like "https://example.com/recipes/".
+    ///
+    /// Required for generating Atom feeds, a sitemap, and canonical and
+    /// alternate links of the pages, which all need absolute links.
+    #[arg(long, value_name = "URL")]
+    pub(crate) base_url: Option<String>,
+    /// Format of error messages.
+    #[arg(long, value_enum, default_value_t)]
+    pub(crate) message_format: MessageFormat,
+    /// Treat warnings as errors.
+    #[arg(long, visible_alias = "deny-warnings", overrides_with = "no_strict")]
+    pub(crate) strict: bool,
+    /// Do not treat warnings as errors, even if set in the configuration.
+    #[arg(long, overrides_with = "strict")]
+    pub(crate) no_strict: bool,
+    /// Do not report this lint.
+    #[arg(long, value_enum, value_name = "LINT")]
+    pub(crate) allow: Vec<Lint>,
+    /// Report this lint as an error.
+    #[arg(long, value_enum, value_name = "LINT")]
+    pub(crate) deny: Vec<Lint>,
+}
+
+impl Options {
+    /// Builder of the website from the source directory with these options.
+    pub(crate) fn site(&self, source: &Path) -> SiteBuilder {
+        let mut site = Site::builder(source)
+            .lang(&self.lang)
+            .prerender(self.prerender.iter().copied())
+            .strict(self.strict);
+        if let Some(templates) = &self.templates {
+            site = site.templates(templates);
+        }
+        if let Some(l10n) = &self.l10n {
+            site = site.l10n(l10n);
+        }
+        if let Some(title) = &self.title {
+            site = site.title(title);
+        }
+        for (label, href) in &self.link {
+            site = site.link(label, href);
+        }
+        if let Some(footer) = &self.footer {
+            site = site.footer(footer);
+        }
+        if let Some(url) = &self.base_url {
+            site = site.base_url(url);
+        }
+        for lint in &self.allow {
+            site = site.allow(*lint);
+        }
+        for lint in &self.deny {
+            site = site.deny(*lint);
+        }
+        site
+    }
+}
+
+/// Parse a positive and finite number of servings.
+fn parse_servings(arg: &str) -> Result<f32> {
+    let servings = arg
+        .parse()
+        .with_context(|| format!(r#"servings "{arg}" are not a number"#))?;
+    check_servings(servings)
+}
+
+/// Reject numbers of servings which cannot be pre-rendered.
+pub(crate) fn check_servings(servings: f32) -> Result<f32> {
+    if !servings.is_finite() || servings <= 0. {
+        bail!("servings {servings} are not positive and finite");
+    }
+    Ok(servings)
 }
 
 /// Parse link of format `label=href`
-fn parse_link(arg: &str) -> Result<Link> {
+pub(crate) fn parse_link(arg: &str) -> Result<(String, String)> {
     let parts = arg
         .split_once('=')
         .with_context(|| format!(r#"link argument "{arg}" does not contain a '='"#))?;
-    Ok(Link {
-        label: parts.0.to_string(),
-        href: parts.1.to_string(),
-    })
+    Ok((parts.0.to_string(), parts.1.to_string()))
 }
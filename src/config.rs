@@ -0,0 +1,307 @@
+use std::{
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use clap::{parser::ValueSource, ArgMatches};
+use serde::Deserialize;
+
+use rumtopf::{Lint, MessageFormat, CONFIG};
+
+use crate::args::{check_servings, parse_link, Args, Command, Options};
+
+/// Defaults for the command-line options, which have the same names.
+///
+/// Relative paths are relative to the directory of the configuration file.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct Config {
+    destination: Option<PathBuf>,
+    remove: Option<bool>,
+    incremental: Option<bool>,
+    port: Option<u16>,
+    title: Option<String>,
+    /// Links shown before the ones from the command line.
+    link: Vec<String>,
+    footer: Option<String>,
+    templates: Option<PathBuf>,
+    lang: Option<String>,
+    l10n: Option<PathBuf>,
+    prerender: Option<Vec<f32>>,
+    base_url: Option<String>,
+    message_format: Option<MessageFormat>,
+    strict: Option<bool>,
+    /// Lints allowed in addition to the ones from the command line unless
+    /// denied there.
+    allow: Vec<Lint>,
+    /// Lints denied in addition to the ones from the command line unless
+    /// allowed there.
+    deny: Vec<Lint>,
+}
+
+/// Fill in options not given on the command line from the configuration file.
+pub(crate) fn apply_config(args: &mut Args, matches: &ArgMatches) -> Result<()> {
+    match &mut args.command {
+        None => {
+            let Some(source) = &args.source else {
+                return Ok(());
+            };
+            let Some(mut config) = read_config(&args.options, source)? else {
+                return Ok(());
+            };
+            merge(
+                &mut args.destination,
+                config.destination.take().map(Some),
+                matches,
+                "destination",
+            );
+            merge(&mut args.remove, config.remove, matches, "remove");
+            merge(
+                &mut args.incremental,
+                config.incremental,
+                matches,
+                "incremental",
+            );
+            merge_options(&mut args.options, config, matches)?;
+            if args.remove && args.incremental {
+                bail!("Options remove and incremental cannot be used together");
+            }
+        }
+        Some(Command::Serve(serve)) => {
+            let matches = matches
+                .subcommand_matches("serve")
+                .expect("serve arguments must have matches");
+            let Some(mut config) = read_config(&serve.options, &serve.source)? else {
+                return Ok(());
+            };
+            merge(
+                &mut serve.destination,
+                config.destination.take().map(Some),
+                matches,
+                "destination",
+            );
+            merge(&mut serve.port, config.port, matches, "port");
+            merge_options(&mut serve.options, config, matches)?;
+        }
+        Some(Command::Check(check)) => {
+            let matches = matches
+                .subcommand_matches("check")
+                .expect("check arguments must have matches");
+            let Some(config) = read_config(&check.options, &check.source)? else {
+                return Ok(());
+            };
+            merge_options(&mut check.options, config, matches)?;
+        }
+        Some(Command::Export(export)) => {
+            let matches = matches
+                .subcommand_matches("export")
+                .expect("export arguments must have matches");
+            let Some(config) = read_config(&export.options, &export.source)? else {
+                return Ok(());
+            };
+            merge_options(&mut export.options, config, matches)?;
+        }
+        Some(Command::New(_)) => {}
+    }
+    Ok(())
+}
+
+/// Read the configuration file given by the options or in the source
+/// directory.
+fn read_config(options: &Options, source: &Path) -> Result<Option<Config>> {
+    let path = match &options.config {
+        Some(path) => path.clone(),
+        None => {
+            let path = source.join(CONFIG);
+            if !path.is_file() {
+                return Ok(None);
+            }
+            path
+        }
+    };
+    let content = read_to_string(&path)
+        .with_context(|| format!("Failed to read configuration {}", path.to_string_lossy()))?;
+    let mut config: Config = toml::from_str(&content)
+        .with_context(|| format!("Failed to parse configuration {}", path.to_string_lossy()))?;
+
+    let dir = path.parent().unwrap_or(Path::new(""));
+    for path in [
+        &mut config.destination,
+        &mut config.templates,
+        &mut config.l10n,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        *path = dir.join(&*path);
+    }
+    Ok(Some(config))
+}
+
+fn merge_options(options: &mut Options, config: Config, matches: &ArgMatches) -> Result<()> {
+    let mut links = config
+        .link
+        .iter()
+        .map(|link| parse_link(link))
+        .collect::<Result<Vec<_>>>()
+        .context("Failed to parse links of configuration")?;
+    links.append(&mut options.link);
+    options.link = links;
+    for &servings in config.prerender.iter().flatten() {
+        check_servings(servings).context("Failed to parse prerender of configuration")?;
+    }
+    merge(&mut options.title, config.title.map(Some), matches, "title");
+    merge(
+        &mut options.footer,
+        config.footer.map(Some),
+        matches,
+        "footer",
+    );
+    merge(
+        &mut options.templates,
+        config.templates.map(Some),
+        matches,
+        "templates",
+    );
+    merge(&mut options.lang, config.lang, matches, "lang");
+    merge(&mut options.l10n, config.l10n.map(Some), matches, "l10n");
+    merge(
+        &mut options.prerender,
+        config.prerender,
+        matches,
+        "prerender",
+    );
+    merge(
+        &mut options.base_url,
+        config.base_url.map(Some),
+        matches,
+        "base_url",
+    );
+    merge(
+        &mut options.message_format,
+        config.message_format,
+        matches,
+        "message_format",
+    );
+    if !options.no_strict {
+        merge(&mut options.strict, config.strict, matches, "strict");
+    }
+    // Lints given on the command line take precedence over the configuration.
+    options.allow.extend(
+        config
+            .allow
+            .into_iter()
+            .filter(|l| !options.deny.contains(l)),
+    );
+    options.deny.extend(
+        config
+            .deny
+            .into_iter()
+            .filter(|l| !options.allow.contains(l)),
+    );
+    Ok(())
+}
+
+/// Use the value of the configuration unless given on the command line.
+fn merge<T>(value: &mut T, config: Option<T>, matches: &ArgMatches, id: &str) {
+    if matches.value_source(id) == Some(ValueSource::CommandLine) {
+        return;
+    }
+    if let Some(config) = config {
+        *value = config;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env::temp_dir,
+        fs::{create_dir_all, remove_dir_all, write},
+        process,
+    };
+
+    use clap::{CommandFactory, FromArgMatches};
+
+    use super::*;
+
+    /// Parse the arguments with the configuration in a new source directory.
+    fn args(name: &str, config: &str, args: &[&str]) -> Result<Args> {
+        let src = temp_dir().join(format!("rumtopf-test-{}-config-{name}", process::id()));
+        let _ = remove_dir_all(&src);
+        create_dir_all(&src).unwrap();
+        write(src.join(CONFIG), config).unwrap();
+
+        let matches = Args::command()
+            .try_get_matches_from(["rumtopf", src.to_str().unwrap()].iter().chain(args))
+            .unwrap();
+        let mut parsed = Args::from_arg_matches(&matches).unwrap();
+        let result = apply_config(&mut parsed, &matches);
+        remove_dir_all(&src).unwrap();
+        result.map(|()| parsed)
+    }
+
+    #[test]
+    fn command_line_takes_precedence() {
+        let config = r#"
+            destination = "public"
+            title = "From File"
+            link = ["A=a.html"]
+            lang = "de"
+        "#;
+        let parsed = args("precedence", config, &[]).unwrap();
+        assert_eq!(parsed.options.title.as_deref(), Some("From File"));
+        assert_eq!(parsed.options.lang, "de");
+        // Relative to the directory of the configuration file.
+        let src = temp_dir().join(format!("rumtopf-test-{}-config-precedence", process::id()));
+        assert_eq!(parsed.destination.unwrap(), src.join("public"));
+
+        let parsed = args(
+            "precedence",
+            config,
+            &["out", "--title", "Given", "--link", "B=b.html"],
+        )
+        .unwrap();
+        assert_eq!(parsed.destination.unwrap(), PathBuf::from("out"));
+        assert_eq!(parsed.options.title.as_deref(), Some("Given"));
+        assert_eq!(parsed.options.lang, "de");
+        let links = parsed.options.link.iter().map(|(label, _)| label.as_str());
+        assert_eq!(links.collect::<Vec<_>>(), ["A", "B"]);
+    }
+
+    #[test]
+    fn lints_and_strict() {
+        let config = r#"
+            strict = true
+            allow = ["missing-servings"]
+            deny = ["case-conflict"]
+        "#;
+        let parsed = args("lints", config, &[]).unwrap();
+        assert!(parsed.options.strict);
+        assert_eq!(parsed.options.allow, [Lint::MissingServings]);
+        assert_eq!(parsed.options.deny, [Lint::CaseConflict]);
+
+        let parsed = args(
+            "lints",
+            config,
+            &[
+                "--no-strict",
+                "--deny",
+                "missing-servings",
+                "--allow",
+                "case-conflict",
+            ],
+        )
+        .unwrap();
+        assert!(!parsed.options.strict);
+        assert_eq!(parsed.options.allow, [Lint::CaseConflict]);
+        assert_eq!(parsed.options.deny, [Lint::MissingServings]);
+    }
+
+    #[test]
+    fn invalid_configuration() {
+        assert!(args("invalid", "unknown = 1", &[]).is_err());
+        assert!(args("invalid", "prerender = [2, -1]", &[]).is_err());
+        assert!(args("invalid", "remove = true\nincremental = true", &[]).is_err());
+    }
+}
@@ -0,0 +1,378 @@
+use std::{
+    cell::RefCell,
+    fmt::Display,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// How diagnostics are printed.
+#[derive(Clone, Copy, Default, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum MessageFormat {
+    /// Compiler-style messages showing the offending line.
+    #[default]
+    Human,
+    /// One JSON object per line.
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        })
+    }
+}
+
+/// Problems which are not necessarily errors and can be allowed or denied.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum Lint {
+    /// A recipe has no level-one heading as title.
+    MissingTitle,
+    /// A text of the templates is not localized for the language of a page.
+    MissingTranslation,
+    /// A recipe has quantities to scale but no number of servings.
+    MissingServings,
+    /// A recipe has more than one number of servings.
+    MultipleServings,
+    /// A quantity comes before the number of servings.
+    QuantityBeforeServings,
+    /// A recipe in another language has no version in the default language.
+    MissingDefaultLang,
+    /// Stems of recipes in a category differ only by case.
+    CaseConflict,
+}
+
+impl Lint {
+    fn default_severity(self) -> Severity {
+        match self {
+            Lint::MissingTitle => Severity::Error,
+            Lint::MissingTranslation => Severity::Warning,
+            Lint::MissingServings => Severity::Note,
+            Lint::MultipleServings
+            | Lint::QuantityBeforeServings
+            | Lint::MissingDefaultLang
+            | Lint::CaseConflict => Severity::Warning,
+        }
+    }
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = self.to_possible_value().expect("lints are never skipped");
+        f.write_str(value.get_name())
+    }
+}
+
+/// An error, warning, or note which might point to a span in a source file.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub struct Diagnostic {
+    #[serde(flatten)]
+    location: Option<Location>,
+    severity: Severity,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lint: Option<Lint>,
+    message: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+struct Location {
+    file: PathBuf,
+    /// Line of the start of the span, starting at 1.
+    line: usize,
+    /// Column in characters of the start of the span, starting at 1.
+    column: usize,
+    end_line: usize,
+    /// Column after the end of the span.
+    end_column: usize,
+    /// Line containing the start of the span.
+    #[serde(skip)]
+    text: String,
+}
+
+impl Diagnostic {
+    pub fn new(err: impl Display) -> Self {
+        Self {
+            location: None,
+            severity: Severity::Error,
+            lint: None,
+            message: format!("{err:#}"),
+        }
+    }
+
+    /// Report the lint with its default severity.
+    pub(crate) fn from_lint(lint: Lint, message: impl Display) -> Self {
+        Self {
+            location: None,
+            severity: lint.default_severity(),
+            lint: Some(lint),
+            message: message.to_string(),
+        }
+    }
+
+    /// Point to the byte range `span` in the source of the file.
+    pub(crate) fn at(mut self, file: &Path, source: &str, span: Range<usize>) -> Self {
+        let (line, column) = line_column(source, span.start);
+        let (end_line, end_column) = line_column(source, span.end.max(span.start));
+        let line_start = source[..span.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[span.start..]
+            .find('\n')
+            .map_or(source.len(), |i| span.start + i);
+        self.location = Some(Location {
+            file: file.to_path_buf(),
+            line,
+            column,
+            end_line,
+            end_column,
+            text: source[line_start..line_end].trim_end().to_string(),
+        });
+        self
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub fn lint(&self) -> Option<Lint> {
+        self.lint
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn file(&self) -> Option<&Path> {
+        self.location.as_ref().map(|l| l.file.as_path())
+    }
+
+    /// Line of the start of the span, starting at 1.
+    pub fn line(&self) -> Option<usize> {
+        self.location.as_ref().map(|l| l.line)
+    }
+
+    /// Column in characters of the start of the span, starting at 1.
+    pub fn column(&self) -> Option<usize> {
+        self.location.as_ref().map(|l| l.column)
+    }
+
+    /// Print the diagnostic to standard error.
+    pub fn print(&self, format: MessageFormat) {
+        match format {
+            MessageFormat::Human => eprintln!("{self}"),
+            MessageFormat::Json => {
+                let json = serde_json::to_string(self).expect("failed to serialize diagnostic");
+                eprintln!("{json}");
+            }
+        }
+    }
+}
+
+/// Compiler-style message showing the first line of the span.
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.severity)?;
+        if let Some(lint) = self.lint {
+            write!(f, "[{lint}]")?;
+        }
+        write!(f, ": {}", self.message)?;
+        let Some(location) = &self.location else {
+            return Ok(());
+        };
+
+        let width = location.line.to_string().len();
+        let gutter = " ".repeat(width);
+        let length = if location.end_line == location.line {
+            location.end_column.saturating_sub(location.column)
+        } else {
+            // The span can start in whitespace trimmed from the text.
+            (location.text.chars().count() + 1).saturating_sub(location.column)
+        };
+        write!(
+            f,
+            "\n{gutter}--> {}:{}:{}\n{gutter} |\n{} | {}\n{gutter} | {}{}\n",
+            location.file.to_string_lossy(),
+            location.line,
+            location.column,
+            location.line,
+            location.text,
+            " ".repeat(location.column - 1),
+            "^".repeat(length.max(1)),
+        )
+    }
+}
+
+/// Line and column in characters of a byte offset, both starting at 1.
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+thread_local! {
+    /// Diagnostics pushed by this thread during [`Diagnostics::capture`].
+    static CAPTURED: RefCell<Option<Vec<Diagnostic>>> = const { RefCell::new(None) };
+}
+
+/// Thread-safe collector of diagnostics which do not stop the generation.
+#[derive(Default)]
+pub(crate) struct Diagnostics {
+    diagnostics: Mutex<Vec<Diagnostic>>,
+    /// Report warnings as errors.
+    strict: bool,
+    allow: Vec<Lint>,
+    deny: Vec<Lint>,
+}
+
+impl Diagnostics {
+    pub(crate) fn new(strict: bool, allow: Vec<Lint>, deny: Vec<Lint>) -> Self {
+        Self {
+            diagnostics: Mutex::default(),
+            strict,
+            allow,
+            deny,
+        }
+    }
+
+    pub(crate) fn error(&self, err: impl Display) {
+        self.push(Diagnostic::new(err));
+    }
+
+    /// Add the diagnostic with the severity of its lint changed by the options.
+    pub(crate) fn push(&self, mut diagnostic: Diagnostic) {
+        CAPTURED.with_borrow_mut(|captured| {
+            if let Some(captured) = captured {
+                captured.push(diagnostic.clone());
+            }
+        });
+        if let Some(lint) = diagnostic.lint {
+            if self.allow.contains(&lint) {
+                return;
+            }
+            if self.deny.contains(&lint) {
+                diagnostic.severity = Severity::Error;
+            }
+        }
+        if self.strict && diagnostic.severity == Severity::Warning {
+            diagnostic.severity = Severity::Error;
+        }
+        self.diagnostics
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(diagnostic);
+    }
+
+    /// Run the function and also return the diagnostics it pushed on this
+    /// thread as they were before applying the options.
+    ///
+    /// Pushing them again later reproduces the diagnostics of the function.
+    pub(crate) fn capture<T>(&self, f: impl FnOnce() -> T) -> (T, Vec<Diagnostic>) {
+        let outer = CAPTURED.replace(Some(Vec::new()));
+        let result = f();
+        let captured = CAPTURED.replace(outer).unwrap_or_default();
+        (result, captured)
+    }
+
+    /// Take all collected diagnostics.
+    ///
+    /// They are sorted to not depend on the order in which the threads
+    /// finished.
+    /// Diagnostics reported for every page, like missing translations, are
+    /// only kept once.
+    pub(crate) fn take(&self) -> Report {
+        let mut diagnostics = std::mem::take(
+            &mut *self
+                .diagnostics
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        );
+        diagnostics.sort();
+        diagnostics.dedup();
+        Report { diagnostics }
+    }
+}
+
+/// Context of a fatal error with the diagnostics collected until it occurred.
+///
+/// Retrieve it from the error with [`anyhow::Error::downcast_ref`].
+#[derive(Debug)]
+pub struct Aborted {
+    report: Report,
+}
+
+impl Aborted {
+    pub(crate) fn new(report: Report) -> Self {
+        Self { report }
+    }
+
+    pub fn report(&self) -> &Report {
+        &self.report
+    }
+}
+
+impl Display for Aborted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Generation aborted")
+    }
+}
+
+/// Diagnostics of generating a website sorted by file and position.
+#[derive(Debug)]
+pub struct Report {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Report {
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Whether any diagnostic is an error.
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|d| d.severity == Severity::Error)
+    }
+
+    pub fn print(&self, format: MessageFormat) {
+        for diagnostic in &self.diagnostics {
+            diagnostic.print(format);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn span_starting_in_trailing_whitespace() {
+        let source = "- @flour{abc}   \nnext line\n";
+        let start = source.find("   ").unwrap() + 2;
+        let diagnostic =
+            Diagnostic::new("message").at(Path::new("a.md"), source, start..source.len());
+        let text = diagnostic.to_string();
+        assert!(
+            text.ends_with(&format!("| {}^\n", " ".repeat(15))),
+            "{text}"
+        );
+    }
+}
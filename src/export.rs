@@ -0,0 +1,464 @@
+use std::{
+    fs::read,
+    io::{Cursor, Write},
+    path::{Path, PathBuf},
+    sync::LazyLock,
+    time::UNIX_EPOCH,
+};
+
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use quick_xml::{
+    events::{BytesStart, Event as XmlEvent},
+    Reader,
+};
+use regex::{Captures, Regex};
+use serde_json::{json, Value};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    files::static_file,
+    manifest::hash,
+    parsing::{render_standalone, RawHtml, Recipe},
+    utils::{category_page, escape_xml, percent_decode, render, rfc3339, slugify, Ctx},
+    writing::template_ctx,
+};
+
+const CONTAINER: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+<rootfiles>
+<rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+</rootfiles>
+</container>
+"#;
+
+/// Stylesheet of the EPUB, which leaves most of the styling to the reader.
+const EPUB_STYLE: &str = ".quantity, .servings { font-weight: bold; }
+.hashtag, .badge { color: gray; }
+ul.list-inline { list-style: none; padding: 0; color: gray; }
+";
+
+/// Recipes to include in an export.
+#[derive(Clone, Default)]
+#[non_exhaustive]
+pub struct Selection {
+    /// Only recipes in this language.
+    ///
+    /// Recipes without a language are in the default language.
+    pub lang: Option<String>,
+    /// Only recipes with any of these tags.
+    pub tags: Vec<String>,
+}
+
+impl Selection {
+    /// Select all recipes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only select recipes in this language.
+    pub fn lang(mut self, lang: impl Into<String>) -> Self {
+        self.lang = Some(lang.into());
+        self
+    }
+
+    /// Also select recipes with this tag.
+    ///
+    /// Without tags, recipes are selected regardless of their tags.
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+
+    pub(crate) fn contains(&self, recipe: &Recipe, default_lang: &str) -> bool {
+        let lang = recipe.lang.as_deref().unwrap_or(default_lang);
+        self.lang.as_ref().is_none_or(|l| l == lang)
+            && (self.tags.is_empty()
+                || recipe
+                    .tags
+                    .iter()
+                    .any(|t| self.tags.iter().any(|s| slugify(s) == slugify(t))))
+    }
+}
+
+/// Sort recipes like in a book, grouped by their categories.
+pub(crate) fn sort_for_book(recipes: &mut [Recipe]) {
+    recipes.sort_by_cached_key(|r| (r.category.clone(), r.title.to_lowercase()));
+}
+
+/// Image elements with their source in either kind of quotes.
+static IMG_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"<img\b[^>]*?\ssrc\s*=\s*(?:"([^"]*)"|'([^']*)')[^>]*>"#)
+        .expect("failed to compile image regex")
+});
+
+/// Anchor of a recipe at the index in a document with all recipes.
+///
+/// The index keeps anchors unique as slugs of different recipes can be equal,
+/// like for `baking/bread.md` and `baking_bread.md`.
+pub(crate) fn recipe_id(index: usize, recipe: &Recipe) -> String {
+    format!(
+        "recipe-{}-{}",
+        index + 1,
+        slugify(&category_page(&recipe.category, &recipe.stem))
+    )
+}
+
+/// Replace the sources of the images in the HTML of a recipe.
+///
+/// `replace` gets the source and, unless it is a URL, the path of the image
+/// relative to the recipe.
+/// It returns the new source or `None` to drop the image.
+fn replace_images(
+    ctx: &Ctx,
+    recipe: &Recipe,
+    html: &str,
+    mut replace: impl FnMut(&str, Option<&Path>) -> Option<String>,
+) -> String {
+    let dir = recipe.path.parent().unwrap_or(&ctx.src);
+    IMG_RE
+        .replace_all(html, |caps: &Captures| {
+            let attr = caps.get(1).or_else(|| caps.get(2)).expect("src is matched");
+            let src = unescape_html(attr.as_str());
+            let path = if src.contains(':') || src.starts_with("//") {
+                None
+            } else if let Some(absolute) = src.strip_prefix('/') {
+                Some(ctx.src.join(percent_decode(absolute)))
+            } else {
+                Some(dir.join(percent_decode(&src)))
+            };
+            let Some(new) = replace(&src, path.as_deref()) else {
+                return String::new();
+            };
+            let tag = caps.get(0).expect("match exists");
+            let (start, end) = (attr.start() - tag.start(), attr.end() - tag.start());
+            format!(
+                "{}{}{}",
+                &tag.as_str()[..start],
+                escape_xml(&new),
+                &tag.as_str()[end..]
+            )
+        })
+        .into_owned()
+}
+
+/// Embed a local image as a data URI.
+fn data_uri(path: &Path) -> Result<String> {
+    let Some(media_type) = image_type(path) else {
+        bail!("Unknown type of image {}", path.to_string_lossy());
+    };
+    let content =
+        read(path).with_context(|| format!("Failed to read image {}", path.to_string_lossy()))?;
+    Ok(format!(
+        "data:{media_type};base64,{}",
+        STANDARD.encode(content)
+    ))
+}
+
+/// Media type of an image supported by browsers and e-book readers.
+fn image_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_string_lossy().to_ascii_lowercase();
+    Some(match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        _ => return None,
+    })
+}
+
+fn unescape_html(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// Data of a recipe with its rendered HTML for the `recipe_body` template
+/// outside the website.
+fn recipe_data(index: usize, recipe: &Recipe, html: String) -> Value {
+    let tags = recipe
+        .tags
+        .iter()
+        .map(|tag| json!({"name": tag, "link": null}))
+        .collect::<Vec<_>>();
+    json!({
+        "id": recipe_id(index, recipe),
+        "title": recipe.title,
+        "category": recipe.category.join(" / "),
+        "recipe": html,
+        "tags": tags,
+        "meta": recipe.meta,
+    })
+}
+
+/// Render the recipes into one self-contained HTML document for printing.
+///
+/// Quantities are shown for the base servings as no scripts are included.
+/// Local images are embedded as data URIs.
+pub(crate) fn html_book(ctx: &Ctx, recipes: &[Recipe], lang: &str) -> Result<String> {
+    let recipes = recipes
+        .iter()
+        .enumerate()
+        .map(|(index, recipe)| {
+            let html = render_standalone(ctx, recipe, RawHtml::Verbatim);
+            let html = replace_images(ctx, recipe, &html, |src, path| {
+                let Some(path) = path else {
+                    return Some(src.to_string());
+                };
+                match data_uri(path) {
+                    Ok(uri) => Some(uri),
+                    Err(err) => {
+                        ctx.diagnostics.error(err.context(format!(
+                            "Skipping embedding image of recipe {}",
+                            recipe.path.to_string_lossy()
+                        )));
+                        Some(src.to_string())
+                    }
+                }
+            });
+            recipe_data(index, recipe, html)
+        })
+        .collect::<Vec<_>>();
+    let css = static_file("bootstrap.min.css").context("Missing built-in stylesheet")?;
+
+    render(
+        ctx.reg,
+        "book",
+        &json!({
+            "ctx": template_ctx(ctx, ""),
+            "lang": lang,
+            "css": String::from_utf8_lossy(css),
+            "recipes": recipes,
+        }),
+    )
+}
+
+/// Package the recipes as an EPUB 3 with one chapter per recipe.
+pub(crate) fn epub(ctx: &Ctx, recipes: &[Recipe], lang: &str) -> Result<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    // Readers identify the file by the uncompressed mimetype at the start.
+    add_file(
+        &mut zip,
+        "mimetype",
+        b"application/epub+zip",
+        CompressionMethod::Stored,
+    )?;
+    add_file(
+        &mut zip,
+        "META-INF/container.xml",
+        CONTAINER.as_bytes(),
+        CompressionMethod::Deflated,
+    )?;
+    add_file(
+        &mut zip,
+        "OEBPS/style.css",
+        EPUB_STYLE.as_bytes(),
+        CompressionMethod::Deflated,
+    )?;
+
+    let mut chapters = Vec::new();
+    let mut images = Vec::new();
+    for (index, recipe) in recipes.iter().enumerate() {
+        let href = recipe_id(index, recipe) + ".xhtml";
+        let chapter = |html: String| {
+            let mut data = recipe_data(index, recipe, html);
+            data["ctx"] = template_ctx(ctx, "");
+            data["lang"] = recipe.lang.as_deref().unwrap_or(lang).into();
+            render(ctx.reg, "epub_chapter", &data)
+        };
+        let mut raw_html = RawHtml::Xhtml;
+        if !well_formed(&chapter(render_standalone(ctx, recipe, raw_html))?) {
+            ctx.diagnostics.error(format!(
+                "Escaping HTML of recipe {}, which is not well-formed XHTML",
+                recipe.path.to_string_lossy()
+            ));
+            raw_html = RawHtml::Escaped;
+        }
+        let html = render_standalone(ctx, recipe, raw_html);
+        let html = replace_images(ctx, recipe, &html, |src, path| {
+            add_image(&mut images, src, path)
+                .with_context(|| {
+                    format!("Skipping image of recipe {}", recipe.path.to_string_lossy())
+                })
+                .map_err(|err| ctx.diagnostics.error(err))
+                .ok()
+        });
+        let chapter = chapter(html)?;
+        add_file(
+            &mut zip,
+            &format!("OEBPS/{href}"),
+            chapter.as_bytes(),
+            CompressionMethod::Deflated,
+        )?;
+        chapters.push(json!({"href": href, "title": recipe.title}));
+    }
+    for image in &images {
+        add_file(
+            &mut zip,
+            &format!("OEBPS/{}", image.href),
+            &image.content,
+            CompressionMethod::Deflated,
+        )?;
+    }
+
+    let nav = render(
+        ctx.reg,
+        "epub_nav",
+        &json!({
+            "ctx": template_ctx(ctx, ""),
+            "lang": lang,
+            "chapters": chapters,
+        }),
+    )?;
+    add_file(
+        &mut zip,
+        "OEBPS/nav.xhtml",
+        nav.as_bytes(),
+        CompressionMethod::Deflated,
+    )?;
+    add_file(
+        &mut zip,
+        "OEBPS/content.opf",
+        package(ctx, recipes, lang, &images).as_bytes(),
+        CompressionMethod::Deflated,
+    )?;
+
+    let zip = zip.finish().context("Failed to package EPUB")?;
+    Ok(zip.into_inner())
+}
+
+/// Image packaged in an EPUB.
+struct Image {
+    path: PathBuf,
+    /// Path relative to the package document.
+    href: String,
+    media_type: &'static str,
+    content: Vec<u8>,
+}
+
+/// Package a local image once and return its path in the EPUB.
+///
+/// E-book readers do not load remote images.
+fn add_image(images: &mut Vec<Image>, src: &str, path: Option<&Path>) -> Result<String> {
+    let Some(path) = path else {
+        bail!("Image {src} is not a local file");
+    };
+    if let Some(image) = images.iter().find(|i| i.path == path) {
+        return Ok(image.href.clone());
+    }
+    let Some(media_type) = image_type(path) else {
+        bail!("Unknown type of image {}", path.to_string_lossy());
+    };
+    let content =
+        read(path).with_context(|| format!("Failed to read image {}", path.to_string_lossy()))?;
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    let href = format!("images/{}.{extension}", images.len() + 1);
+    images.push(Image {
+        path: path.to_path_buf(),
+        href: href.clone(),
+        media_type,
+        content,
+    });
+    Ok(href)
+}
+
+/// Whether the document is well-formed XML without entities undefined in XML.
+fn well_formed(xml: &str) -> bool {
+    let mut reader = Reader::from_str(xml);
+    let mut depth = 0usize;
+    loop {
+        let attributes_ok = |e: &BytesStart| {
+            e.attributes()
+                .all(|a| a.is_ok_and(|a| a.unescape_value().is_ok()))
+        };
+        match reader.read_event() {
+            Ok(XmlEvent::Start(e)) if attributes_ok(&e) => depth += 1,
+            Ok(XmlEvent::Empty(e)) if attributes_ok(&e) => {}
+            Ok(XmlEvent::Start(_) | XmlEvent::Empty(_)) | Err(_) => return false,
+            Ok(XmlEvent::End(_)) => depth -= 1,
+            Ok(XmlEvent::GeneralRef(r)) => {
+                let predefined = matches!(&*r, b"lt" | b"gt" | b"amp" | b"quot" | b"apos");
+                if !predefined && !r.resolve_char_ref().is_ok_and(|c| c.is_some()) {
+                    return false;
+                }
+            }
+            Ok(XmlEvent::Eof) => return depth == 0,
+            Ok(_) => {}
+        }
+    }
+}
+
+fn add_file(
+    zip: &mut ZipWriter<Cursor<Vec<u8>>>,
+    name: &str,
+    content: &[u8],
+    compression: CompressionMethod,
+) -> Result<()> {
+    let options = SimpleFileOptions::default().compression_method(compression);
+    zip.start_file(name, options)
+        .and_then(|()| Ok(zip.write_all(content)?))
+        .with_context(|| format!("Failed to add {name} to EPUB"))
+}
+
+/// Package document listing the metadata and all files of the EPUB.
+fn package(ctx: &Ctx, recipes: &[Recipe], lang: &str, images: &[Image]) -> String {
+    let title = ctx.title.as_deref().unwrap_or("Recipes");
+    let stems = recipes
+        .iter()
+        .map(|r| category_page(&r.category, &r.stem))
+        .collect::<Vec<_>>();
+    // Identify the book by its recipes so that readers update it.
+    let id = format!("urn:rumtopf:{:016x}", hash(&(title, lang, &stems)));
+    let modified = recipes
+        .iter()
+        .filter_map(|r| r.modified)
+        .max()
+        .unwrap_or(UNIX_EPOCH);
+
+    let mut items = String::new();
+    let mut spine = String::new();
+    for (index, recipe) in recipes.iter().enumerate() {
+        let id = recipe_id(index, recipe);
+        items += &format!(
+            "<item id=\"{id}\" href=\"{id}.xhtml\" media-type=\"application/xhtml+xml\"/>\n"
+        );
+        spine += &format!("<itemref idref=\"{id}\"/>\n");
+    }
+    for (index, image) in images.iter().enumerate() {
+        items += &format!(
+            "<item id=\"image-{}\" href=\"{}\" media-type=\"{}\"/>\n",
+            index + 1,
+            image.href,
+            image.media_type
+        );
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="id" xml:lang="{lang}">
+<metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+<dc:identifier id="id">{id}</dc:identifier>
+<dc:title>{title}</dc:title>
+<dc:language>{lang}</dc:language>
+<meta property="dcterms:modified">{modified}</meta>
+</metadata>
+<manifest>
+<item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+<item id="style" href="style.css" media-type="text/css"/>
+{items}</manifest>
+<spine>
+<itemref idref="nav"/>
+{spine}</spine>
+</package>
+"#,
+        lang = escape_xml(lang),
+        title = escape_xml(title),
+        modified = rfc3339(modified),
+    )
+}
@@ -0,0 +1,101 @@
+use std::time::UNIX_EPOCH;
+
+use anyhow::Context;
+
+use crate::{
+    utils::{escape_xml, rfc3339, Ctx, Rtx},
+    writing::write_rendered,
+    Recipe,
+};
+
+/// Feed to link from the pages of a language, if feeds are generated.
+pub(crate) fn feed_link(ctx: &Ctx, rtx: &Rtx, lang: Option<&str>) -> Option<String> {
+    ctx.base_url.as_ref().map(|_| rtx.feed(lang))
+}
+
+/// Write one Atom feed per language with the newest recipes first.
+///
+/// Like the search indices, recipes without a language are included in every
+/// feed.
+/// Feeds need absolute links and are therefore only written with a base URL.
+pub(crate) fn write_feeds(ctx: &Ctx, rtx: &Rtx) {
+    let Some(base) = &ctx.base_url else {
+        return;
+    };
+    for &lang in rtx.langs.iter() {
+        let mut entries = rtx
+            .recipes
+            .iter()
+            .filter(|r| r.lang.is_none() || r.lang.as_deref() == lang)
+            .map(|r| (date(r), r))
+            .collect::<Vec<_>>();
+        entries.sort_by(|(a, r), (b, s)| b.cmp(a).then_with(|| r.title.cmp(&s.title)));
+
+        let name = rtx.feed(lang);
+        let xml = atom(ctx, base, &name, lang.unwrap_or(rtx.default_lang), &entries);
+        if let Err(err) = write_rendered(ctx, &name, &xml, || Ok(xml.clone()))
+            .with_context(|| format!("Skipping writing feed {name}"))
+        {
+            ctx.diagnostics.error(err);
+        }
+    }
+}
+
+/// Entries without an author inherit the author of the feed, which is the
+/// title of the website.
+fn atom(ctx: &Ctx, base: &str, name: &str, lang: &str, entries: &[(String, &Recipe)]) -> String {
+    let title = ctx.title.as_deref().unwrap_or("Recipes");
+    let updated = entries
+        .first()
+        .map_or(rfc3339(UNIX_EPOCH), |(d, _)| d.clone());
+    let mut xml = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom" xml:lang="{}">
+<title>{title}</title>
+<id>{base}{name}</id>
+<link rel="self" href="{base}{name}"/>
+<link href="{base}"/>
+<updated>{updated}</updated>
+<author><name>{title}</name></author>
+"#,
+        escape_xml(lang),
+        title = escape_xml(title),
+        base = escape_xml(base),
+        name = escape_xml(name),
+    );
+    for (date, recipe) in entries {
+        let link = escape_xml(&format!("{base}{}", recipe.link));
+        xml += &format!(
+            r#"<entry>
+<title>{}</title>
+<id>{link}</id>
+<link href="{link}"/>
+<updated>{date}</updated>
+"#,
+            escape_xml(&recipe.title),
+        );
+        if let Some(author) = &recipe.meta.author {
+            xml += &format!("<author><name>{}</name></author>\n", escape_xml(author));
+        }
+        let summary = recipe
+            .meta
+            .description
+            .as_ref()
+            .or_else(|| recipe.steps.first());
+        if let Some(summary) = summary {
+            xml += &format!("<summary>{}</summary>\n", escape_xml(summary));
+        }
+        xml += "</entry>\n";
+    }
+    xml += "</feed>\n";
+    xml
+}
+
+/// Date of the front matter or else the modification time of the recipe.
+fn date(recipe: &Recipe) -> String {
+    recipe
+        .meta
+        .date
+        .clone()
+        .unwrap_or_else(|| rfc3339(recipe.modified.unwrap_or(UNIX_EPOCH)))
+}
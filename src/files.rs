@@ -1,17 +1,31 @@
-use std::fs::write;
-
 use anyhow::Context;
 
-use crate::utils::Ctx;
+use crate::{units::units_js, utils::Ctx};
 
 include!(concat!(env!("OUT_DIR"), "/files.rs"));
 
-pub(crate) fn create_static(ctx: &mut Ctx) {
-    for (name, content) in STATIC {
-        if let Err(err) = write(ctx.dest.join(name), content)
-            .with_context(|| format!("Failed to write static file {name}"))
-        {
-            ctx.print_error(err);
+/// Content of a built-in static file.
+pub(crate) fn static_file(name: &str) -> Option<&'static [u8]> {
+    STATIC.iter().find(|(n, _)| *n == name).map(|(_, c)| *c)
+}
+
+pub(crate) fn create_static(ctx: &Ctx) {
+    let units = units_js();
+    let files = STATIC
+        .iter()
+        .copied()
+        .chain([("units.js", units.as_bytes())]);
+    for (name, content) in files {
+        let inputs = ctx.inputs(&content);
+        if let Some(output) = ctx.output.filter(|_| !ctx.is_fresh(name, inputs)) {
+            if let Err(err) = output
+                .write(name, content)
+                .with_context(|| format!("Failed to write static file {name}"))
+            {
+                ctx.diagnostics.error(err);
+                continue;
+            }
         }
+        ctx.record(name, inputs);
     }
 }
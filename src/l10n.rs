@@ -2,23 +2,31 @@ use std::{
     collections::{hash_map::Entry, HashMap},
     fs::File,
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
 use anyhow::{Context, Result};
 use handlebars::{no_escape, Handlebars, HelperDef, RenderError, RenderErrorReason};
 use serde_json::json;
 
+use crate::diagnostics::{Diagnostic, Diagnostics, Lint};
+
 const L10N: &[u8] = include_bytes!("l10n.json");
 
 pub(crate) struct L10nHelper {
     templates: Handlebars<'static>,
     fallback_lang: String,
+    diagnostics: Arc<Diagnostics>,
 }
 
 type Raw = HashMap<String, HashMap<String, String>>;
 
 impl L10nHelper {
-    pub(crate) fn new(custom: Option<PathBuf>, fallback_lang: String) -> Result<Self> {
+    pub(crate) fn new(
+        custom: Option<PathBuf>,
+        fallback_lang: String,
+        diagnostics: Arc<Diagnostics>,
+    ) -> Result<Self> {
         let mut l10n: Raw =
             serde_json::from_slice(L10N).context("failed to parse included l10n.json")?;
         if let Some(custom) = custom {
@@ -41,6 +49,7 @@ impl L10nHelper {
         Ok(Self {
             templates,
             fallback_lang,
+            diagnostics,
         })
     }
 }
@@ -96,7 +105,13 @@ impl HelperDef for L10nHelper {
                 if self.templates.has_template(&template) {
                     Some(template)
                 } else {
-                    eprintln!("missing lang {lang} for template {key}, trying fallback…");
+                    self.diagnostics.push(Diagnostic::from_lint(
+                        Lint::MissingTranslation,
+                        format!(
+                            "Missing localization {key} for language {lang}, using {}",
+                            self.fallback_lang
+                        ),
+                    ));
                     None
                 }
             }
@@ -0,0 +1,615 @@
+//! Generator for a static recipe website.
+//!
+//! A [`Site`] is configured with a [`SiteBuilder`] and renders the recipes of
+//! a source directory into an [`Output`] like a [`Directory`].
+//! Problems which do not stop the generation are returned as a [`Report`].
+
+mod diagnostics;
+mod export;
+mod feed;
+mod files;
+mod l10n;
+mod lints;
+mod manifest;
+mod meta;
+mod output;
+mod parsing;
+mod quantity;
+mod scaffold;
+mod units;
+mod utils;
+mod writing;
+
+use std::{
+    fs::{read, read_dir, DirEntry},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use anyhow::{bail, Context, Result};
+use diagnostics::Diagnostics;
+use export::{epub, html_book, sort_for_book};
+use feed::write_feeds;
+use files::*;
+use handlebars::Handlebars;
+use l10n::L10nHelper;
+use lints::lint_recipes;
+use manifest::*;
+use parsing::*;
+use rayon::prelude::*;
+use utils::*;
+use writing::{
+    write_indices, write_recipes, write_search_indices, write_shopping_lists, write_sitemap,
+    write_tags,
+};
+
+pub use diagnostics::{Aborted, Diagnostic, Lint, MessageFormat, Report, Severity};
+pub use export::Selection;
+pub use manifest::MANIFEST;
+pub use meta::{Meta, Yield};
+pub use output::{Directory, Memory, Output};
+pub use parsing::Recipe;
+pub use quantity::{Ingredient, Quantity, Rounding, Scaling};
+pub use scaffold::NewRecipe;
+pub use utils::percent_decode;
+
+/// Name of the configuration file in the source directory, which is never
+/// copied to the output.
+pub const CONFIG: &str = "rumtopf.toml";
+
+/// Options of a website set by the [`SiteBuilder`].
+#[derive(Clone)]
+struct Settings {
+    source: PathBuf,
+    templates: Option<PathBuf>,
+    l10n: Option<PathBuf>,
+    title: Option<String>,
+    links: Vec<Link>,
+    footer: Option<String>,
+    lang: String,
+    prerender: Vec<f32>,
+    base_url: Option<String>,
+    strict: bool,
+    allow: Vec<Lint>,
+    deny: Vec<Lint>,
+    incremental: bool,
+}
+
+/// Builder of a [`Site`] for a source directory with recipes in Markdown
+/// format.
+pub struct SiteBuilder {
+    settings: Settings,
+}
+
+impl SiteBuilder {
+    /// Directory with HTML templates to override built-in ones.
+    pub fn templates(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.settings.templates = Some(dir.into());
+        self
+    }
+
+    /// Custom localization file to override the built-in one.
+    pub fn l10n(mut self, file: impl Into<PathBuf>) -> Self {
+        self.settings.l10n = Some(file.into());
+        self
+    }
+
+    /// Custom website title.
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.settings.title = Some(title.into());
+        self
+    }
+
+    /// Add a link to the footer.
+    pub fn link(mut self, label: impl Into<String>, href: impl Into<String>) -> Self {
+        self.settings.links.push(Link {
+            label: label.into(),
+            href: href.into(),
+        });
+        self
+    }
+
+    /// Plain text in the footer.
+    pub fn footer(mut self, footer: impl Into<String>) -> Self {
+        self.settings.footer = Some(footer.into());
+        self
+    }
+
+    /// Language for language selection index and uncategorized recipes.
+    ///
+    /// Defaults to `en`.
+    pub fn lang(mut self, lang: impl Into<String>) -> Self {
+        self.settings.lang = lang.into();
+        self
+    }
+
+    /// Pre-render recipe pages for these numbers of servings.
+    ///
+    /// [`SiteBuilder::build`] fails unless all numbers are positive and finite.
+    pub fn prerender(mut self, servings: impl IntoIterator<Item = f32>) -> Self {
+        self.settings.prerender = servings.into_iter().collect();
+        self
+    }
+
+    /// URL of the website for absolute links in the Atom feeds, the sitemap,
+    /// and the canonical and alternate links of the pages.
+    ///
+    /// These are only generated with a base URL.
+    pub fn base_url(mut self, url: impl Into<String>) -> Self {
+        let mut url = url.into();
+        if !url.ends_with('/') {
+            url.push('/');
+        }
+        self.settings.base_url = Some(url);
+        self
+    }
+
+    /// Treat warnings as errors.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.settings.strict = strict;
+        self
+    }
+
+    /// Do not report this lint.
+    pub fn allow(mut self, lint: Lint) -> Self {
+        self.settings.allow.push(lint);
+        self
+    }
+
+    /// Report this lint as an error.
+    pub fn deny(mut self, lint: Lint) -> Self {
+        self.settings.deny.push(lint);
+        self
+    }
+
+    /// Only render files whose inputs changed since the previous generation
+    /// into the same output.
+    ///
+    /// Files whose sources were removed are removed from the output.
+    pub fn incremental(mut self, incremental: bool) -> Self {
+        self.settings.incremental = incremental;
+        self
+    }
+
+    /// Load the templates and the localization.
+    pub fn build(self) -> Result<Site> {
+        let settings = self.settings;
+        if let Some(servings) = settings
+            .prerender
+            .iter()
+            .find(|s| !s.is_finite() || **s <= 0.)
+        {
+            bail!("Cannot pre-render pages for {servings} servings");
+        }
+        let diagnostics = Arc::new(Diagnostics::new(
+            settings.strict,
+            settings.allow.clone(),
+            settings.deny.clone(),
+        ));
+        let mut reg = handlebars_registry(settings.templates.as_deref())?;
+        reg.register_helper(
+            "l10n",
+            Box::new(L10nHelper::new(
+                settings.l10n.clone(),
+                settings.lang.clone(),
+                diagnostics.clone(),
+            )?),
+        );
+        Ok(Site {
+            global: global_inputs(&settings),
+            settings,
+            reg,
+            diagnostics,
+        })
+    }
+}
+
+/// A website generated from a source directory.
+///
+/// Methods take `&mut self` as the diagnostics of the localization are
+/// collected for the whole site.
+pub struct Site {
+    settings: Settings,
+    reg: Handlebars<'static>,
+    diagnostics: Arc<Diagnostics>,
+    /// Hash of the inputs shared by all generated files.
+    global: u64,
+}
+
+impl Site {
+    /// Start building a website from the source directory with the default
+    /// options.
+    pub fn builder(source: impl Into<PathBuf>) -> SiteBuilder {
+        SiteBuilder {
+            settings: Settings {
+                source: source.into(),
+                templates: None,
+                l10n: None,
+                title: None,
+                links: Vec::new(),
+                footer: None,
+                lang: "en".to_string(),
+                prerender: Vec::new(),
+                base_url: None,
+                strict: false,
+                allow: Vec::new(),
+                deny: Vec::new(),
+                incremental: false,
+            },
+        }
+    }
+
+    /// Generate the website into the output.
+    ///
+    /// Source files not ending in `.md` are copied to the output.
+    pub fn render(&mut self, output: &dyn Output) -> Result<Report> {
+        self.generate(Some(output))
+    }
+
+    /// Render all pages only in memory to find problems without any output.
+    pub fn check(&mut self) -> Result<Report> {
+        self.generate(None)
+    }
+
+    /// Parse a recipe as if it was the file `<stem>.md` in the source
+    /// directory.
+    pub fn parse_recipe(&mut self, stem: &str, markdown: &str) -> (Recipe, Report) {
+        let ctx = self.ctx(None);
+        let path = self.settings.source.join(format!("{stem}.md"));
+        let recipe = parse_source(&ctx, markdown.to_string(), &path, stem, &[]);
+        (recipe, self.diagnostics.take())
+    }
+
+    /// Render the selected recipes into one self-contained HTML document for
+    /// printing.
+    pub fn html_book(&mut self, selection: &Selection) -> Result<(String, Report)> {
+        self.export(selection, html_book)
+    }
+
+    /// Package the selected recipes as an EPUB with one chapter per recipe.
+    ///
+    /// An EPUB has one language, which defaults to the language given by
+    /// [`SiteBuilder::lang`].
+    pub fn epub(&mut self, selection: &Selection) -> Result<(Vec<u8>, Report)> {
+        let selection = Selection {
+            lang: Some(
+                selection
+                    .lang
+                    .clone()
+                    .unwrap_or_else(|| self.settings.lang.clone()),
+            ),
+            ..selection.clone()
+        };
+        self.export(&selection, epub)
+    }
+
+    /// Parse the selected recipes and export them in the language of the
+    /// selection.
+    fn export<T>(
+        &mut self,
+        selection: &Selection,
+        export: impl FnOnce(&Ctx, &[Recipe], &str) -> Result<T>,
+    ) -> Result<(T, Report)> {
+        // Drop diagnostics of a previous generation which failed.
+        self.diagnostics.take();
+        let ctx = self.ctx(None);
+        let lang = selection.lang.as_deref().unwrap_or(&self.settings.lang);
+        let result = process_source_dir(&ctx).and_then(|mut recipes| {
+            recipes.retain(|r| selection.contains(r, &self.settings.lang));
+            sort_for_book(&mut recipes);
+            export(&ctx, &recipes, lang)
+        });
+        self.finish(result)
+    }
+
+    /// Take the diagnostics and attach them to a fatal error as [`Aborted`].
+    fn finish<T>(&self, result: Result<T>) -> Result<(T, Report)> {
+        let report = self.diagnostics.take();
+        match result {
+            Ok(value) => Ok((value, report)),
+            Err(err) => Err(err.context(Aborted::new(report))),
+        }
+    }
+
+    fn ctx<'l>(&'l self, output: Option<&'l dyn Output>) -> Ctx<'l> {
+        Ctx {
+            src: self.settings.source.clone(),
+            reg: &self.reg,
+            output,
+            diagnostics: self.diagnostics.clone(),
+            title: self.settings.title.clone(),
+            links: self.settings.links.clone(),
+            footer: self.settings.footer.clone().unwrap_or_default(),
+            prerender: self.settings.prerender.clone(),
+            base_url: self.settings.base_url.clone(),
+            previous: None,
+            manifest: Mutex::new(Manifest::new(self.global)),
+            urls: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Generate the website and return the problems found.
+    ///
+    /// Incremental generation keeps files of the previous generation whose
+    /// inputs did not change.
+    fn generate(&mut self, output: Option<&dyn Output>) -> Result<Report> {
+        // Drop diagnostics of a previous generation which failed.
+        self.diagnostics.take();
+        let result = self.write_site(output);
+        self.finish(result).map(|((), report)| report)
+    }
+
+    fn write_site(&self, output: Option<&dyn Output>) -> Result<()> {
+        let mut ctx = self.ctx(output);
+        if let Some(output) = output.filter(|_| self.settings.incremental) {
+            ctx.previous = Some(read_manifest(output)?);
+        }
+        create_static(&ctx);
+
+        // Copy source files after creating static files to allow overriding them.
+        let mut recipes = process_source_dir(&ctx)?;
+        recipes.sort_unstable();
+        let rtx = Rtx::new(&recipes, &self.settings.lang);
+        lint_recipes(&ctx, &rtx);
+
+        write_recipes(&ctx, &rtx);
+        write_indices(&ctx, &rtx);
+        write_tags(&ctx, &rtx);
+        write_search_indices(&ctx, &rtx);
+        write_shopping_lists(&ctx, &rtx);
+        write_feeds(&ctx, &rtx);
+        write_sitemap(&ctx);
+
+        let manifest = ctx
+            .manifest
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(output) = output {
+            if let Some(previous) = &ctx.previous {
+                remove_stale(output, previous, &manifest)?;
+            }
+            write_manifest(output, &manifest)?;
+        }
+        Ok(())
+    }
+}
+
+/// Hash of the inputs shared by all generated files.
+///
+/// Built-in templates and assets only change with the version.
+fn global_inputs(settings: &Settings) -> u64 {
+    let mut templates = settings
+        .templates
+        .as_deref()
+        .and_then(|dir| read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| (entry.path(), read(entry.path()).ok()))
+        .collect::<Vec<_>>();
+    templates.sort_unstable();
+    let l10n = settings.l10n.as_deref().map(|path| read(path).ok());
+
+    hash(&(
+        env!("CARGO_PKG_VERSION"),
+        &settings.title,
+        &settings.links,
+        &settings.footer,
+        &settings.lang,
+        settings
+            .prerender
+            .iter()
+            .map(|p| p.to_bits())
+            .collect::<Vec<_>>(),
+        &settings.base_url,
+        templates,
+        l10n,
+    ))
+}
+
+/// A recipe found in the source directory.
+struct Source {
+    path: PathBuf,
+    category: Vec<String>,
+}
+
+fn process_source_dir(ctx: &Ctx) -> Result<Vec<Recipe>> {
+    let mut sources = vec![];
+    process_dir(ctx, &ctx.src, &mut Vec::new(), &mut sources)?;
+
+    let recipes = sources
+        .par_iter()
+        .filter_map(|source| {
+            parse_file(ctx, &source.path, &source.category)
+                .with_context(|| {
+                    format!("Skipping failed source {}", source.path.to_string_lossy())
+                })
+                .map_err(|err| ctx.diagnostics.error(err))
+                .ok()
+        })
+        .collect();
+    Ok(recipes)
+}
+
+/// Process a directory of the source tree with subdirectories as categories.
+/// Copy files and collect recipes to parse them in parallel afterwards.
+fn process_dir(
+    ctx: &Ctx,
+    dir: &Path,
+    category: &mut Vec<String>,
+    sources: &mut Vec<Source>,
+) -> Result<()> {
+    let entries = read_dir(dir)
+        .with_context(|| format!("Failed to read source directory {}", dir.to_string_lossy()))?;
+
+    for entry in entries {
+        let entry = entry.with_context(|| {
+            format!(
+                "Failed to list contents of source directory {}",
+                dir.to_string_lossy()
+            )
+        });
+        if let Err(err) = entry.and_then(|entry| {
+            process_source_entry(ctx, &entry, category, sources).with_context(|| {
+                format!("Skipping failed source {}", entry.path().to_string_lossy())
+            })
+        }) {
+            ctx.diagnostics.error(err);
+        }
+    }
+    Ok(())
+}
+
+fn process_source_entry(
+    ctx: &Ctx,
+    entry: &DirEntry,
+    category: &mut Vec<String>,
+    sources: &mut Vec<Source>,
+) -> Result<()> {
+    let path = entry.path();
+    let name = entry.file_name().to_string_lossy().into_owned();
+    let typ = entry.file_type().context("Failed to query file type")?;
+    if typ.is_dir() {
+        // Skip directories like .git.
+        if name.starts_with('.') {
+            return Ok(());
+        }
+
+        category.push(name);
+        let result = process_dir(ctx, &path, category, sources);
+        category.pop();
+        return result;
+    }
+    if !typ.is_file() {
+        bail!("Source is neither a file nor a directory");
+    }
+    if category.is_empty() && name == CONFIG {
+        return Ok(());
+    }
+
+    if !path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("md"))
+    {
+        let name = category_page(category, &name);
+        let meta = entry.metadata().context("Failed to query metadata")?;
+        let inputs = ctx.inputs(&(meta.len(), meta.modified().ok()));
+        if let Some(output) = ctx.output.filter(|_| !ctx.is_fresh(&name, inputs)) {
+            let content = read(&path).context("Failed to read file")?;
+            output
+                .write(&name, &content)
+                .context("Failed to copy file")?;
+        }
+        ctx.record(&name, inputs);
+        return Ok(());
+    }
+
+    sources.push(Source {
+        path,
+        category: category.clone(),
+    });
+    Ok(())
+}
+
+fn handlebars_registry(override_path: Option<&Path>) -> Result<Handlebars<'static>> {
+    let mut reg = Handlebars::new();
+    reg.set_strict_mode(true);
+    // Indenting partials would change the content of `<pre>` elements.
+    reg.set_prevent_indent(true);
+
+    for (name, content) in TEMPLATES {
+        reg.register_template_string(name, content)
+            .expect("failed to register template");
+    }
+
+    if let Some(path) = override_path {
+        let dir = read_dir(path).with_context(|| {
+            format!(
+                "Failed to read template directory {}",
+                path.to_string_lossy()
+            )
+        })?;
+
+        for entry in dir {
+            let entry = entry.with_context(|| {
+                format!(
+                    "Failed to list contents of template directory {}",
+                    path.to_string_lossy()
+                )
+            })?;
+            process_template(&entry, &mut reg).with_context(|| {
+                format!(
+                    "Failed to process template file {}",
+                    entry.path().to_string_lossy()
+                )
+            })?;
+        }
+    }
+
+    Ok(reg)
+}
+
+fn process_template(entry: &DirEntry, reg: &mut Handlebars) -> Result<()> {
+    let path = entry.path();
+    let name = path
+        .file_stem()
+        .context("File without file name")?
+        .to_string_lossy();
+    if name.starts_with('.') {
+        return Ok(());
+    }
+
+    let typ = entry.file_type().context("Failed to query file type")?;
+    if !typ.is_file() {
+        return Ok(());
+    }
+
+    reg.register_template_file(&name, &path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env::temp_dir,
+        fs::{create_dir_all, remove_dir_all, remove_file, write},
+        process,
+    };
+
+    use super::*;
+
+    #[test]
+    fn incremental_generation() {
+        let src = temp_dir().join(format!("rumtopf-test-{}-incremental", process::id()));
+        let _ = remove_dir_all(&src);
+        create_dir_all(&src).unwrap();
+        write(src.join("pizza.md"), "# Pizza\n\n{{2 servings}}\n").unwrap();
+        write(src.join("tarte.fr.md"), "# Tarte\n\n{{2 servings}}\n").unwrap();
+
+        let output = Memory::new();
+        let mut site = Site::builder(&src).incremental(true).build().unwrap();
+        let first = site.render(&output).unwrap();
+        assert!(first
+            .diagnostics()
+            .iter()
+            .any(|d| d.lint() == Some(Lint::MissingTranslation)));
+
+        // Pages with unchanged inputs are not written again, but their
+        // diagnostics are still reported.
+        output.write("pizza.html", b"unchanged").unwrap();
+        output.write("tarte.fr.html", b"unchanged").unwrap();
+        let second = site.render(&output).unwrap();
+        assert_eq!(second.diagnostics(), first.diagnostics());
+        assert_eq!(output.read("pizza.html").unwrap().unwrap(), b"unchanged");
+
+        write(src.join("pizza.md"), "# Pizza\n\n{{4 servings}}\n").unwrap();
+        remove_file(src.join("tarte.fr.md")).unwrap();
+        site.render(&output).unwrap();
+        assert_ne!(output.read("pizza.html").unwrap().unwrap(), b"unchanged");
+        assert!(!output.exists("tarte.fr.html"));
+        assert!(output.exists(MANIFEST));
+
+        remove_dir_all(&src).unwrap();
+    }
+}
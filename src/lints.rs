@@ -0,0 +1,57 @@
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+
+use crate::{
+    diagnostics::{Diagnostic, Lint},
+    parsing::Recipe,
+    utils::{Ctx, Rtx},
+};
+
+/// Report problems involving several recipes.
+pub(crate) fn lint_recipes(ctx: &Ctx, rtx: &Rtx) {
+    let is_default = |r: &Recipe| r.lang.as_deref().is_none_or(|l| l == rtx.default_lang);
+    let defaults = rtx
+        .recipes
+        .iter()
+        .filter(|r| is_default(r))
+        .map(|r| (&r.category, &r.short))
+        .collect::<HashSet<_>>();
+    let mut reported = HashSet::new();
+    let mut stems = HashMap::new();
+
+    for recipe in rtx.recipes {
+        let short = (&recipe.category, &recipe.short);
+        if !is_default(recipe) && !defaults.contains(&short) && reported.insert(short) {
+            report(
+                ctx,
+                recipe,
+                Lint::MissingDefaultLang,
+                format!(
+                    "Recipe {} has no version in the default language {}",
+                    recipe.short, rtx.default_lang
+                ),
+            );
+        }
+
+        match stems.entry((&recipe.category, recipe.stem.to_lowercase())) {
+            Entry::Occupied(entry) => report(
+                ctx,
+                recipe,
+                Lint::CaseConflict,
+                format!(
+                    "Recipe {} differs from {} only by case, which breaks on \
+                     case-insensitive file systems",
+                    recipe.stem,
+                    entry.get()
+                ),
+            ),
+            Entry::Vacant(entry) => {
+                entry.insert(&recipe.stem);
+            }
+        }
+    }
+}
+
+fn report(ctx: &Ctx, recipe: &Recipe, lint: Lint, message: String) {
+    ctx.diagnostics
+        .push(Diagnostic::from_lint(lint, message).at(&recipe.path, &recipe.source, 0..0));
+}
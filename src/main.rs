@@ -1,65 +1,150 @@
 mod args;
-mod files;
-mod l10n;
-mod parsing;
-mod utils;
-mod writing;
+mod config;
+mod serve;
 
 use std::{
-    fs::{create_dir, read_dir, remove_dir_all, DirEntry},
+    fs::{create_dir, read_dir, remove_dir_all, write},
     io::ErrorKind,
     path::Path,
     process::ExitCode,
 };
 
 use anyhow::{bail, Context, Result};
-use args::Args;
-use clap::Parser;
-use files::*;
-use handlebars::Handlebars;
-use l10n::L10nHelper;
-use parsing::*;
-use utils::*;
-use writing::{write_indices, write_recipes};
-
-fn main() -> Result<ExitCode> {
-    let args = Args::parse();
-    let mut reg = handlebars_registry(args.templates.as_deref())?;
-    reg.register_helper(
-        "l10n",
-        Box::new(L10nHelper::new(args.l10n, args.lang.clone())?),
-    );
-    let mut ctx = Ctx {
-        src: args.source,
-        reg,
-        dest: args.destination,
-        any_error: false,
-        title: args.title,
-        links: args.link,
-        footer: args.footer.unwrap_or_default(),
+use args::{Args, Command, ExportArgs, ExportFormat, Options};
+use clap::{CommandFactory, FromArgMatches};
+use config::apply_config;
+use rumtopf::{Aborted, Diagnostic, Directory, MessageFormat, NewRecipe, Selection, MANIFEST};
+use serve::serve;
+
+fn main() -> ExitCode {
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    let config = apply_config(&mut args, &matches);
+    let format = match &args.command {
+        Some(Command::Serve(args)) => args.options.message_format,
+        Some(Command::Check(args)) => args.options.message_format,
+        Some(Command::Export(args)) => args.options.message_format,
+        Some(Command::New(_)) => MessageFormat::Human,
+        None => args.options.message_format,
     };
+    config.and_then(|()| run(args)).unwrap_or_else(|err| {
+        print_error(&err, format);
+        ExitCode::FAILURE
+    })
+}
 
-    if args.remove {
-        remove_dest(&ctx.dest)?;
+/// Print a fatal error after the diagnostics collected before it.
+fn print_error(err: &anyhow::Error, format: MessageFormat) {
+    if let Some(aborted) = err.downcast_ref::<Aborted>() {
+        aborted.report().print(format);
     }
-    create_dest(&ctx.dest)?;
-    create_static(&mut ctx);
-
-    // Copy source files after creating static files to allow overriding them.
-    let mut recipes = process_source_dir(&mut ctx)?;
-    recipes.sort_unstable();
-    let rtx = Rtx::new(&recipes, &args.lang);
+    Diagnostic::new(err).print(format);
+}
 
-    write_recipes(&mut ctx, &rtx);
-    write_indices(&mut ctx, &rtx);
+fn run(args: Args) -> Result<ExitCode> {
+    let any_error = match args.command {
+        Some(Command::Serve(args)) => return serve(args),
+        Some(Command::New(args)) => {
+            let mut recipe = NewRecipe::new(&args.source, &args.title);
+            if let Some(lang) = &args.lang {
+                recipe = recipe.lang(lang);
+            }
+            if let Some(skeleton) = &args.skeleton {
+                recipe = recipe.skeleton(skeleton);
+            }
+            if let Some(l10n) = &args.l10n {
+                recipe = recipe.l10n(l10n);
+            }
+            let (path, report) = recipe.create()?;
+            report.print(MessageFormat::Human);
+            println!("{}", path.to_string_lossy());
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Export(args)) => export(args)?,
+        Some(Command::Check(args)) => {
+            let report = args.options.site(&args.source).build()?.check()?;
+            report.print(args.options.message_format);
+            report.has_errors()
+        }
+        None => {
+            let Some(src) = args.source else {
+                unreachable!("clap requires source without subcommand");
+            };
+            let Some(dest) = args.destination else {
+                bail!("Destination directory must be given as argument or in the configuration");
+            };
+            if args.remove {
+                remove_dest(&dest)?;
+            }
+            generate(&src, &dest, &args.options, args.incremental)?
+        }
+    };
 
-    Ok(if ctx.any_error {
+    Ok(if any_error {
         ExitCode::from(2)
     } else {
         ExitCode::SUCCESS
     })
 }
 
+/// Generate the website and return whether any non-fatal error occurred.
+///
+/// Incremental generation requires an existing destination to be a previous
+/// generation.
+fn generate(src: &Path, dest: &Path, options: &Options, incremental: bool) -> Result<bool> {
+    let mut site = options.site(src).incremental(incremental).build()?;
+    if incremental && dest.exists() {
+        check_previous(dest)?;
+    } else {
+        create_dest(dest)?;
+    }
+
+    let report = site.render(&Directory::new(dest))?;
+    report.print(options.message_format);
+    Ok(report.has_errors())
+}
+
+/// Export the selected recipes and return whether any non-fatal error
+/// occurred.
+fn export(args: ExportArgs) -> Result<bool> {
+    let mut site = args.options.site(&args.source).build()?;
+    let mut selection = Selection::new();
+    if let Some(lang) = args.only_lang {
+        selection = selection.lang(lang);
+    }
+    for tag in args.only_tag {
+        selection = selection.tag(tag);
+    }
+    let (content, report) = match args.format {
+        ExportFormat::HtmlBook => {
+            let (html, report) = site.html_book(&selection)?;
+            (html.into_bytes(), report)
+        }
+        ExportFormat::Epub => site.epub(&selection)?,
+    };
+    write(&args.output, content)
+        .with_context(|| format!("Failed to write export {}", args.output.to_string_lossy()))?;
+    report.print(args.options.message_format);
+    Ok(report.has_errors())
+}
+
+/// Refuse to generate incrementally into a directory with other contents.
+fn check_previous(dest: &Path) -> Result<()> {
+    let mut entries = read_dir(dest).with_context(|| {
+        format!(
+            "Failed to read destination directory {}",
+            dest.to_string_lossy()
+        )
+    })?;
+    if !dest.join(MANIFEST).exists() && entries.next().is_some() {
+        bail!(
+            "Destination directory {} is not empty but has no {MANIFEST}",
+            dest.to_string_lossy()
+        );
+    }
+    Ok(())
+}
+
 fn remove_dest(path: &Path) -> Result<()> {
     remove_dir_all(path)
         .or_else(|e| {
@@ -85,114 +170,3 @@ fn create_dest(dest: &Path) -> Result<()> {
         )
     })
 }
-
-fn process_source_dir(ctx: &mut Ctx) -> Result<Vec<Recipe>> {
-    let sources = read_dir(&ctx.src).with_context(|| {
-        format!(
-            "Failed to read source directory {}",
-            ctx.src.to_string_lossy()
-        )
-    })?;
-
-    let mut recipes = vec![];
-    for entry in sources {
-        let entry = entry.with_context(|| {
-            format!(
-                "Failed to list contents of source directory {}",
-                ctx.src.to_string_lossy()
-            )
-        });
-        let recipe = entry.and_then(|entry| {
-            process_source_entry(ctx, &entry).with_context(|| {
-                format!("Skipping failed source {}", entry.path().to_string_lossy())
-            })
-        });
-        let recipe = match recipe {
-            Ok(r) => r,
-            Err(err) => {
-                ctx.print_error(err);
-                continue;
-            }
-        };
-
-        if let Some(recipe) = recipe {
-            recipes.push(recipe);
-        }
-    }
-    Ok(recipes)
-}
-
-fn process_source_entry(ctx: &mut Ctx, entry: &DirEntry) -> Result<Option<Recipe>> {
-    let typ = entry.file_type().context("Failed to query file type")?;
-    if !typ.is_file() {
-        bail!("Source is not a file");
-    }
-
-    let path = entry.path();
-    if !path
-        .extension()
-        .and_then(|extension| extension.to_str())
-        .is_some_and(|extension| extension.eq_ignore_ascii_case("md"))
-    {
-        std::fs::copy(&path, Path::new(&ctx.dest).join(path.file_name().unwrap()))
-            .context("Failed to copy file")?;
-        return Ok(None);
-    }
-
-    Ok(Some(parse_file(ctx, &path)?))
-}
-
-fn handlebars_registry(override_path: Option<&Path>) -> Result<Handlebars<'static>> {
-    let mut reg = Handlebars::new();
-    reg.set_strict_mode(true);
-
-    for (name, content) in TEMPLATES {
-        reg.register_template_string(name, content)
-            .expect("failed to register template");
-    }
-
-    if let Some(path) = override_path {
-        let dir = read_dir(path).with_context(|| {
-            format!(
-                "Failed to read template directory {}",
-                path.to_string_lossy()
-            )
-        })?;
-
-        for entry in dir {
-            let entry = entry.with_context(|| {
-                format!(
-                    "Failed to list contents of template directory {}",
-                    path.to_string_lossy()
-                )
-            })?;
-            process_template(&entry, &mut reg).with_context(|| {
-                format!(
-                    "Failed to process template file {}",
-                    entry.path().to_string_lossy()
-                )
-            })?;
-        }
-    }
-
-    Ok(reg)
-}
-
-fn process_template(entry: &DirEntry, reg: &mut Handlebars) -> Result<()> {
-    let path = entry.path();
-    let name = path
-        .file_stem()
-        .context("File without file name")?
-        .to_string_lossy();
-    if name.starts_with('.') {
-        return Ok(());
-    }
-
-    let typ = entry.file_type().context("Failed to query file type")?;
-    if !typ.is_file() {
-        return Ok(());
-    }
-
-    reg.register_template_file(&name, &path)?;
-    Ok(())
-}
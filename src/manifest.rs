@@ -0,0 +1,79 @@
+use std::{
+    collections::BTreeMap,
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{diagnostics::Diagnostic, output::Output};
+
+/// File in the destination directory storing the manifest.
+pub const MANIFEST: &str = ".rumtopf-manifest.json";
+
+/// Hashes of the inputs of all generated files for incremental generation.
+///
+/// The hashes are not stable across versions of Rust, which only results in
+/// a full generation.
+#[derive(Default, Deserialize, Serialize)]
+pub(crate) struct Manifest {
+    /// Hash of the inputs shared by all files like templates and options.
+    pub(crate) global: u64,
+    /// Hashes of the inputs of each file relative to the destination.
+    pub(crate) outputs: BTreeMap<String, u64>,
+    /// Diagnostics of rendering each file, which are reported again when the
+    /// file is not rendered.
+    ///
+    /// They never point into sources, so their location lacks the text.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) diagnostics: BTreeMap<String, Vec<Diagnostic>>,
+}
+
+impl Manifest {
+    pub(crate) fn new(global: u64) -> Self {
+        Self {
+            global,
+            outputs: BTreeMap::new(),
+            diagnostics: BTreeMap::new(),
+        }
+    }
+}
+
+pub(crate) fn hash(value: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Read the manifest of a previous generation into the output.
+///
+/// An output without a manifest counts as a generation without any files.
+pub(crate) fn read_manifest(output: &dyn Output) -> Result<Manifest> {
+    match output.read(MANIFEST).context("Failed to read manifest")? {
+        Some(content) => serde_json::from_slice(&content).context("Failed to parse manifest"),
+        None => Ok(Manifest::default()),
+    }
+}
+
+pub(crate) fn write_manifest(output: &dyn Output, manifest: &Manifest) -> Result<()> {
+    let json = serde_json::to_string(manifest).context("Failed to serialize manifest")?;
+    output
+        .write(MANIFEST, json.as_bytes())
+        .context("Failed to write manifest")
+}
+
+/// Remove files of the previous generation which were not generated again.
+pub(crate) fn remove_stale(
+    output: &dyn Output,
+    previous: &Manifest,
+    current: &Manifest,
+) -> Result<()> {
+    for name in previous.outputs.keys() {
+        if !current.outputs.contains_key(name) {
+            output
+                .remove(name)
+                .with_context(|| format!("Failed to remove stale file {name}"))?;
+        }
+    }
+    Ok(())
+}
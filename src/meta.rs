@@ -0,0 +1,189 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use pulldown_cmark::MetadataBlockKind;
+use serde::{de::Error, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+use crate::utils::{days_from_civil, days_in_month, format_utc};
+
+/// Metadata from the optional front matter block of a recipe.
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct Meta {
+    pub author: Option<String>,
+    pub tags: Vec<String>,
+    /// Preparation time in minutes.
+    pub prep_time: Option<u32>,
+    /// Cooking time in minutes.
+    pub cook_time: Option<u32>,
+    #[serde(rename = "yield")]
+    pub yields: Option<Yield>,
+    pub source: Option<String>,
+    pub description: Option<String>,
+    /// Publication date in RFC 3339 format in UTC like `2024-05-01T00:00:00Z`.
+    ///
+    /// Dates without a time are taken as midnight UTC.
+    #[serde(deserialize_with = "deserialize_date")]
+    pub date: Option<String>,
+    /// Unknown keys for use in custom templates.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+#[non_exhaustive]
+pub enum Yield {
+    Count(u32),
+    Text(String),
+}
+
+/// Parse YAML (`---`) or TOML (`+++`) front matter.
+pub(crate) fn parse_meta(kind: MetadataBlockKind, text: &str) -> Result<Meta> {
+    if text.trim().is_empty() {
+        return Ok(Meta::default());
+    }
+
+    match kind {
+        MetadataBlockKind::YamlStyle => {
+            serde_yaml_ng::from_str(text).context("Failed to parse YAML front matter")
+        }
+        MetadataBlockKind::PlusesStyle => {
+            toml::from_str(text).context("Failed to parse TOML front matter")
+        }
+    }
+}
+
+/// Date as a string or as a TOML date.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawDate {
+    Text(String),
+    Toml(toml::value::Datetime),
+}
+
+fn deserialize_date<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    let date = match RawDate::deserialize(deserializer)? {
+        RawDate::Text(text) => text,
+        RawDate::Toml(date) => date.to_string(),
+    };
+    normalize_date(&date).map(Some).ok_or_else(|| {
+        D::Error::custom(format!(
+            r#"invalid date "{date}", expected YYYY-MM-DD or RFC 3339"#
+        ))
+    })
+}
+
+/// Complete a date with an optional time and offset to RFC 3339 in UTC.
+///
+/// Converting to UTC keeps dates in the same order as their strings.
+/// Fractions of seconds are dropped.
+fn normalize_date(date: &str) -> Option<String> {
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    let (day, time) = match date.split_once(['T', 't', ' ']) {
+        Some((day, time)) => (day, Some(time)),
+        None => (date, None),
+    };
+    let parts = day.split('-').collect::<Vec<_>>();
+    let valid_day = parts.len() == 3
+        && parts
+            .iter()
+            .zip([4, 2, 2])
+            .all(|(p, len)| p.len() == len && digits(p));
+    if !valid_day {
+        return None;
+    }
+    let [year, month, day] = [0, 1, 2].map(|i| parts[i].parse::<i64>().unwrap_or_default());
+    if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    let Some(time) = time else {
+        return Some(format_utc(days * 86400));
+    };
+
+    let (clock, offset) = match time.find(['Z', 'z', '+', '-']) {
+        Some(i) => time.split_at(i),
+        None => (time, "Z"),
+    };
+    let (clock, fraction) = clock.split_once('.').unwrap_or((clock, "0"));
+    let clock = clock.split(':').collect::<Vec<_>>();
+    let valid_clock = matches!(clock.len(), 2 | 3)
+        && clock.iter().all(|p| p.len() == 2 && digits(p))
+        && digits(fraction);
+    let valid_offset = match offset {
+        "Z" | "z" => true,
+        _ => offset[1..]
+            .split_once(':')
+            .is_some_and(|(h, m)| h.len() == 2 && m.len() == 2 && digits(h) && digits(m)),
+    };
+    if !valid_clock || !valid_offset {
+        return None;
+    }
+    let number = |s: &str| s.parse::<i64>().unwrap_or_default();
+    let [hours, minutes, seconds] = [0, 1, 2].map(|i| clock.get(i).map_or(0, |p| number(p)));
+    // Leap seconds are accepted but counted as the next second.
+    if hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+    let offset = match offset.split_at(1) {
+        ("+" | "-", rest) => {
+            let (h, m) = rest.split_once(':').unwrap_or_default();
+            let (h, m) = (number(h), number(m));
+            if h > 23 || m > 59 {
+                return None;
+            }
+            let sign = if offset.starts_with('-') { -1 } else { 1 };
+            sign * (h * 3600 + m * 60)
+        }
+        _ => 0,
+    };
+    Some(format_utc(
+        days * 86400 + hours * 3600 + minutes * 60 + seconds - offset,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_are_normalized_to_utc() {
+        for (date, normalized) in [
+            ("2024-05-01", "2024-05-01T00:00:00Z"),
+            ("2024-05-01T12:30", "2024-05-01T12:30:00Z"),
+            ("2024-05-01 12:30:15.25", "2024-05-01T12:30:15Z"),
+            ("2024-05-01t12:30:15z", "2024-05-01T12:30:15Z"),
+            ("2024-05-01T10:00:00+02:00", "2024-05-01T08:00:00Z"),
+            ("2024-05-01T23:30:00-01:00", "2024-05-02T00:30:00Z"),
+            ("2024-03-01T00:30:00+01:00", "2024-02-29T23:30:00Z"),
+            ("1969-12-31T23:59:59Z", "1969-12-31T23:59:59Z"),
+        ] {
+            assert_eq!(normalize_date(date).as_deref(), Some(normalized), "{date}");
+        }
+    }
+
+    #[test]
+    fn invalid_dates() {
+        for date in [
+            "",
+            "2024-5-1",
+            "2024-05",
+            "2024-05-01-01",
+            "2024-13-01",
+            "2023-02-29",
+            "2024-04-31",
+            "2024-05-01T24:00",
+            "2024-05-01T12",
+            "2024-05-01T12:30+0200",
+            "2024-05-01T12:30+24:00",
+            "May 1st",
+        ] {
+            assert_eq!(normalize_date(date), None, "{date}");
+        }
+    }
+}
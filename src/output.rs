@@ -0,0 +1,137 @@
+use std::{
+    collections::BTreeMap,
+    fs::{create_dir_all, read, remove_dir, remove_file, write},
+    io::ErrorKind,
+    path::PathBuf,
+    sync::{Mutex, PoisonError},
+};
+
+use anyhow::{Context, Result};
+
+/// Sink for the files of a generated website.
+///
+/// Names of files are paths relative to the root of the website separated by
+/// `/`.
+/// Only [`Output::write`] is required; the other methods enable incremental
+/// generation.
+pub trait Output: Sync {
+    /// Write a file, replacing any previous content.
+    fn write(&self, name: &str, content: &[u8]) -> Result<()>;
+
+    /// Read a file written before, if it exists.
+    fn read(&self, _name: &str) -> Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    /// Whether a file written before still exists.
+    fn exists(&self, _name: &str) -> bool {
+        false
+    }
+
+    /// Remove a file written before which is no longer generated.
+    fn remove(&self, _name: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Write the website to a directory, creating subdirectories as needed.
+pub struct Directory {
+    root: PathBuf,
+}
+
+impl Directory {
+    /// Write to the directory, which is created if it does not exist.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl Output for Directory {
+    fn write(&self, name: &str, content: &[u8]) -> Result<()> {
+        let path = self.root.join(name);
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent).with_context(|| {
+                format!("Failed to create directory {}", parent.to_string_lossy())
+            })?;
+        }
+        write(&path, content)
+            .with_context(|| format!("Failed to write file {}", path.to_string_lossy()))
+    }
+
+    fn read(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.root.join(name);
+        match read(&path) {
+            Ok(content) => Ok(Some(content)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => {
+                Err(err).with_context(|| format!("Failed to read file {}", path.to_string_lossy()))
+            }
+        }
+    }
+
+    fn exists(&self, name: &str) -> bool {
+        self.root.join(name).is_file()
+    }
+
+    /// Remove the file and the directories left empty.
+    fn remove(&self, name: &str) -> Result<()> {
+        let path = self.root.join(name);
+        match remove_file(&path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => {
+                return Err(err)
+                    .with_context(|| format!("Failed to remove file {}", path.to_string_lossy()));
+            }
+            _ => {}
+        }
+        for dir in path.ancestors().skip(1) {
+            if dir == self.root || remove_dir(dir).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Keep the website in memory, for example to post-process or test it.
+#[derive(Default)]
+pub struct Memory {
+    files: Mutex<BTreeMap<String, Vec<u8>>>,
+}
+
+impl Memory {
+    /// Start without any files.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All files written so far by name.
+    pub fn into_files(self) -> BTreeMap<String, Vec<u8>> {
+        self.files
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn files(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, Vec<u8>>> {
+        self.files.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Output for Memory {
+    fn write(&self, name: &str, content: &[u8]) -> Result<()> {
+        self.files().insert(name.to_string(), content.to_vec());
+        Ok(())
+    }
+
+    fn read(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.files().get(name).cloned())
+    }
+
+    fn exists(&self, name: &str) -> bool {
+        self.files().contains_key(name)
+    }
+
+    fn remove(&self, name: &str) -> Result<()> {
+        self.files().remove(name);
+        Ok(())
+    }
+}
@@ -1,27 +1,76 @@
-use std::{fs::read_to_string, path::Path};
+use std::{
+    fs::{metadata, read_to_string},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+    time::SystemTime,
+};
 
-use anyhow::{Context, Error, Result};
-use handlebars::html_escape;
-use pulldown_cmark::{html::push_html, Event, HeadingLevel, Parser, Tag, TagEnd};
+use anyhow::{anyhow, Context, Result};
+use handlebars::{html_escape, Handlebars};
+use pulldown_cmark::{
+    html::push_html, Event, HeadingLevel, MetadataBlockKind, OffsetIter, Options, Parser, Tag,
+    TagEnd,
+};
 use regex::{Captures, Regex};
 use serde::Serialize;
 use serde_json::json;
 
-use crate::utils::*;
+use crate::{
+    diagnostics::{Diagnostic, Lint},
+    meta::*,
+    quantity::*,
+    units::lookup,
+    utils::*,
+};
 
+/// A parsed recipe.
 #[derive(Serialize)]
-pub(crate) struct Recipe {
-    pub(crate) title: String,
-    pub(crate) stem: String,
-    pub(crate) short: String,
+#[non_exhaustive]
+pub struct Recipe {
+    /// Text of the level-one heading or the file name without one.
+    pub title: String,
+    /// File name without `.md`, like `pizza.de`.
+    pub stem: String,
+    /// File stem without the language, like `pizza`.
+    pub short: String,
+    /// Subdirectories of the source directory containing the recipe.
+    pub category: Vec<String>,
+    /// Path of the recipe page relative to the destination directory.
+    pub link: String,
+    /// Rendered HTML of the recipe.
     #[serde(skip)]
-    pub(crate) recipe: String,
-    pub(crate) lang: Option<String>,
+    pub recipe: String,
+    /// Language from the file name like `de` for `pizza.de.md`.
+    pub lang: Option<String>,
+    pub meta: Meta,
+    /// Tags from the front matter and inline `#tag`s.
+    pub tags: Vec<String>,
+    /// Ingredients given with the `@name{quantity}` syntax.
+    pub ingredients: Vec<Ingredient>,
+    /// Plain text of the recipe for searching.
+    #[serde(skip)]
+    pub text: String,
+    /// Base number of servings from the first `{{N servings}}`.
+    pub servings: Option<f32>,
+    /// Plain text of the paragraphs and list items of the instructions
+    /// following the ingredients.
+    #[serde(skip)]
+    pub steps: Vec<String>,
+    /// Markdown source for rendering other numbers of servings.
+    #[serde(skip)]
+    pub source: String,
+    /// Path of the source file.
+    #[serde(skip)]
+    pub path: PathBuf,
+    /// Modification time of the source file, if known.
+    #[serde(skip)]
+    pub modified: Option<SystemTime>,
 }
 
 impl PartialEq for Recipe {
     fn eq(&self, other: &Self) -> bool {
-        self.stem == other.stem
+        self.stem == other.stem && self.category == other.category
     }
 }
 
@@ -29,7 +78,9 @@ impl Eq for Recipe {}
 
 impl Ord for Recipe {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.stem.cmp(&other.stem)
+        self.stem
+            .cmp(&other.stem)
+            .then_with(|| self.category.cmp(&other.category))
     }
 }
 
@@ -39,172 +90,668 @@ impl PartialOrd for Recipe {
     }
 }
 
-pub(crate) fn parse_file(ctx: &mut Ctx, path: &Path) -> Result<Recipe> {
+pub(crate) fn parse_file(ctx: &Ctx, path: &Path, category: &[String]) -> Result<Recipe> {
     let stem = path
         .file_stem()
         .context("File without file name")?
         .to_string_lossy();
+    let source = read_to_string(path).context("Failed to read file")?;
+    let mut recipe = parse_source(ctx, source, path, &stem, category);
+    recipe.modified = metadata(path).and_then(|m| m.modified()).ok();
+    Ok(recipe)
+}
+
+/// Parse the Markdown source of a recipe and report its problems.
+pub(crate) fn parse_source(
+    ctx: &Ctx,
+    source: String,
+    path: &Path,
+    stem: &str,
+    category: &[String],
+) -> Recipe {
     let (short, lang) = match stem.rsplit_once(".") {
         Some((short, lang)) => (short.to_string(), Some(lang.to_string())),
         None => (stem.to_string(), None),
     };
+    let root = root_for(category);
 
-    let source = read_to_string(path).context("Failed to read file")?;
-
-    let mut parser = ServingWrapper::new(Parser::new(&source), ctx, path, lang.as_deref());
+    let mut parser = ServingWrapper::new(
+        &source,
+        ctx.reg,
+        lang.as_deref(),
+        stem,
+        &root,
+        &ctx.prerender,
+        None,
+    );
     let mut recipe = String::new();
     push_html(&mut recipe, &mut parser);
+    let mut diagnostics = std::mem::take(&mut parser.diagnostics);
+    let title = parser.title.trim().to_string();
+    let missing_title = title.is_empty();
+    if missing_title {
+        diagnostics.push((
+            Diagnostic::from_lint(
+                Lint::MissingTitle,
+                "Recipe has no title, using the file name instead",
+            ),
+            0..0,
+        ));
+    }
+    if let Some(span) = parser.quantity.take().filter(|_| parser.servings.is_none()) {
+        diagnostics.push((
+            Diagnostic::from_lint(
+                Lint::MissingServings,
+                "Quantities cannot be scaled without {{N servings}}",
+            ),
+            span,
+        ));
+    }
+
+    let mut tags: Vec<String> = Vec::new();
+    for tag in parser.meta.tags.iter().chain(&parser.tags) {
+        if !slugify(tag).is_empty() && !tags.iter().any(|t| slugify(t) == slugify(tag)) {
+            tags.push(tag.clone());
+        }
+    }
+
+    let text = parser.text.split_whitespace().collect::<Vec<_>>().join(" ");
 
-    Ok(Recipe {
-        title: parser.title,
+    let recipe = Recipe {
+        title: if missing_title { short.clone() } else { title },
+        meta: parser.meta,
+        tags,
+        ingredients: parser.ingredients,
+        text,
+        servings: parser.servings,
+        steps: parser.steps,
+        link: category_page(category, &(stem.to_string() + ".html")),
         stem: stem.to_string(),
         short,
+        category: category.to_vec(),
         recipe,
         lang,
-    })
+        source,
+        path: path.to_path_buf(),
+        modified: None,
+    };
+
+    for (diagnostic, span) in diagnostics {
+        ctx.diagnostics
+            .push(diagnostic.at(path, &recipe.source, span));
+    }
+    recipe
+}
+
+/// Render the recipe again scaled to another number of servings.
+///
+/// Errors are ignored as they were already reported by [`parse_file`].
+pub(crate) fn render_servings(ctx: &Ctx, recipe: &Recipe, servings: f32) -> String {
+    let scale = recipe.servings.map(|base| Scale {
+        base,
+        target: servings,
+    });
+    let root = root_for(&recipe.category);
+    let mut parser = ServingWrapper::new(
+        &recipe.source,
+        ctx.reg,
+        recipe.lang.as_deref(),
+        &recipe.stem,
+        &root,
+        &ctx.prerender,
+        scale,
+    );
+    let mut html = String::new();
+    push_html(&mut html, &mut parser);
+    html
+}
+
+/// How raw HTML written in recipes is rendered.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RawHtml {
+    Verbatim,
+    /// Close void elements like `<br>` and replace common named entities, as
+    /// XHTML requires.
+    Xhtml,
+    /// Show the HTML as text.
+    Escaped,
+}
+
+/// Void elements of HTML, which are never closed.
+static VOID_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"(?i)<(area|base|br|col|embed|hr|img|input|link|meta|source|track|wbr)\b((?:[^>"'/]|"[^"]*"|'[^']*'|/[^>])*?)\s*/?>"#,
+    )
+    .expect("failed to compile void element regex")
+});
+
+static ENTITY_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"&([A-Za-z][A-Za-z0-9]*);").expect("failed to compile entity regex")
+});
+
+/// Common named entities of HTML, which are not defined in XHTML.
+const ENTITIES: &[(&str, u32)] = &[
+    ("nbsp", 160),
+    ("shy", 173),
+    ("deg", 176),
+    ("frac14", 188),
+    ("frac12", 189),
+    ("frac34", 190),
+    ("times", 215),
+    ("ndash", 8211),
+    ("mdash", 8212),
+    ("hellip", 8230),
+    ("copy", 169),
+    ("laquo", 171),
+    ("raquo", 187),
+    ("euro", 8364),
+];
+
+/// Make common HTML well-formed XHTML.
+fn to_xhtml(html: &str) -> String {
+    let html = VOID_RE.replace_all(html, |caps: &Captures| {
+        format!("<{}{} />", caps[1].to_ascii_lowercase(), &caps[2])
+    });
+    ENTITY_RE
+        .replace_all(&html, |caps: &Captures| {
+            match ENTITIES.iter().find(|(name, _)| *name == &caps[1]) {
+                Some((_, code)) => format!("&#{code};"),
+                None => caps[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+/// Render a recipe at its base servings for a standalone document.
+pub(crate) fn render_standalone(ctx: &Ctx, recipe: &Recipe, raw_html: RawHtml) -> String {
+    let mut parser = ServingWrapper::new(
+        &recipe.source,
+        ctx.reg,
+        recipe.lang.as_deref(),
+        &recipe.stem,
+        "",
+        &[],
+        None,
+    );
+    parser.standalone = true;
+    parser.raw_html = raw_html;
+    let mut html = String::new();
+    push_html(&mut html, &mut parser);
+    html
+}
+
+const OPTIONS: Options = Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
+    .union(Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS);
+
+/// Scale from the base number of servings of a recipe to another one.
+#[derive(Clone, Copy)]
+struct Scale {
+    base: f32,
+    target: f32,
+}
+
+#[derive(Serialize)]
+struct ServingsPage {
+    servings: String,
+    link: String,
+    current: bool,
 }
 
-struct ServingWrapper<'l, 'c, I>
-where
-    I: 'l,
-{
-    iter: I,
-    scaling_re: Regex,
-    servings_re: Regex,
-    ctx: &'l mut Ctx<'c>,
-    path: &'l Path,
+/// Extended syntax within text, one alternative per kind of markup.
+const MARKUP: &str = r"(?x)
+    \{\{(?P<servings>[^}]+)\s+servings?\s*\}\}
+    | \{\{\s*(?P<scaling>[^}]+?)\s*\}\}
+    | @(?P<ingredient>[^\s@{}][^@{}]*?)\{(?P<quantity>[^{}]*)\}(?:\((?P<note>[^)]*)\))?
+    | (?P<space>^|\s)\#(?P<tag>\p{L}[\w-]*)
+";
+
+static MARKUP_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(MARKUP).expect("failed to compile markup regex"));
+
+struct ServingWrapper<'l, 'c> {
+    iter: OffsetIter<'l>,
+    source: &'l str,
+    /// Byte range in the source of the current event.
+    range: Range<usize>,
+    reg: &'l Handlebars<'c>,
     lang: Option<&'l str>,
+    stem: &'l str,
+    /// Relative path from the recipe page to the destination directory.
+    root: &'l str,
+    prerender: &'l [f32],
+    scale: Option<Scale>,
+    /// Render for a standalone document without scripts and links to other
+    /// pages.
+    standalone: bool,
+    raw_html: RawHtml,
+    /// Problems with the byte range in the source they refer to.
+    diagnostics: Vec<(Diagnostic, Range<usize>)>,
+    servings: Option<f32>,
+    /// Span of the first scalable quantity.
+    quantity: Option<Range<usize>>,
     title: String,
     in_title: bool,
+    tags: Vec<String>,
+    ingredients: Vec<Ingredient>,
+    text: String,
+    in_code_block: bool,
+    in_link: usize,
+    meta: Meta,
+    front_matter: Option<(MetadataBlockKind, String)>,
+    in_meta: bool,
+    started: bool,
+    steps: Vec<String>,
+    /// Steps of the current section, which is delimited by headings.
+    section: Vec<String>,
+    /// Whether the current section declares ingredients or servings.
+    section_ingredients: bool,
+    /// Whether any previous section declared ingredients or servings.
+    after_ingredients: bool,
+    /// Level of the heading of the section following the ingredients, which
+    /// holds the instructions together with its subsections.
+    instructions: Option<HeadingLevel>,
+    /// Whether a section at the level of the instructions followed them.
+    after_instructions: bool,
+    /// Nesting of paragraphs and list items with the start of the outermost
+    /// one in `text`.
+    block: (usize, usize),
 }
 
-impl<'l, 'c, I> ServingWrapper<'l, 'c, I> {
-    pub(crate) fn new(
-        iter: I,
-        ctx: &'l mut Ctx<'c>,
-        path: &'l Path,
+impl<'l, 'c> ServingWrapper<'l, 'c> {
+    fn new(
+        source: &'l str,
+        reg: &'l Handlebars<'c>,
         lang: Option<&'l str>,
+        stem: &'l str,
+        root: &'l str,
+        prerender: &'l [f32],
+        scale: Option<Scale>,
     ) -> Self {
         Self {
-            iter,
-            scaling_re: Regex::new(r"\{\{\s*([^}]+)\s*\}\}")
-                .expect("failed to compile scaling regex"),
-            servings_re: Regex::new(r"\{\{([^}]+)\s+servings?\s*\}\}")
-                .expect("failed to compile servings regex"),
-            ctx,
-            path,
+            iter: Parser::new_ext(source, OPTIONS).into_offset_iter(),
+            source,
+            range: 0..0,
+            reg,
             lang,
+            stem,
+            root,
+            prerender,
+            scale,
+            standalone: false,
+            raw_html: RawHtml::Verbatim,
+            diagnostics: Vec::new(),
+            servings: None,
+            quantity: None,
             title: String::new(),
             in_title: false,
+            tags: Vec::new(),
+            ingredients: Vec::new(),
+            text: String::new(),
+            in_code_block: false,
+            in_link: 0,
+            meta: Meta::default(),
+            front_matter: None,
+            in_meta: false,
+            started: false,
+            steps: Vec::new(),
+            section: Vec::new(),
+            section_ingredients: false,
+            after_ingredients: false,
+            instructions: None,
+            after_instructions: false,
+            block: (0, 0),
+        }
+    }
+
+    fn start_block(&mut self) {
+        if self.block.0 == 0 {
+            self.block.1 = self.text.len();
+        }
+        self.block.0 += 1;
+    }
+
+    fn end_block(&mut self) {
+        self.block.0 -= 1;
+        if self.block.0 == 0 {
+            let step = self.text[self.block.1..]
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            if !step.is_empty() {
+                self.section.push(step);
+            }
+        }
+    }
+
+    /// Keep the steps of the instructions following the ingredients.
+    fn end_section(&mut self) {
+        let section = std::mem::take(&mut self.section);
+        if self.section_ingredients {
+            // Recipes can alternate between ingredients and instructions.
+            self.after_ingredients = true;
+            self.instructions = None;
+            self.after_instructions = false;
+        } else if self.after_ingredients && !self.after_instructions {
+            self.steps.extend(section);
+        }
+        self.section_ingredients = false;
+    }
+
+    /// Stop collecting steps at sections like notes or sources which follow
+    /// the instructions.
+    fn start_section(&mut self, level: HeadingLevel) {
+        if !self.after_ingredients {
+            return;
+        }
+        match self.instructions {
+            None => self.instructions = Some(level),
+            Some(instructions) if level <= instructions => self.after_instructions = true,
+            Some(_) => {}
         }
     }
 
-    fn replace(&mut self, unescaped: &str) -> Result<String> {
+    /// Replace the markup in text of the current event.
+    ///
+    /// `offset` is the position of the text in the source if it appears there
+    /// verbatim. Otherwise, errors refer to the whole event.
+    fn replace(&mut self, unescaped: &str, hashtags: bool, offset: Option<usize>) -> String {
         if self.in_title {
             self.title.push_str(unescaped);
         }
+        let hashtags = hashtags && !self.in_title && !self.in_code_block && self.in_link == 0;
 
-        let text = html_escape(unescaped);
-        let text = self.servings_re.replace_all(&text, |caps: &Captures| {
-            let servings = &caps[1];
-            let replacement = servings
-                .parse()
-                .with_context(|| format!(r#"Failed to parse servings {}"#, servings))
-                .and_then(|_: f32| {
-                    render(
-                        &self.ctx.reg,
-                        "servings",
-                        &json!({"servings": servings, "lang": self.lang}),
-                    )
-                });
-            match replacement {
-                Ok(t) => t,
-                Err(err) => {
-                    Self::print_error(err, self.path, self.ctx);
-                    caps[0].to_owned()
-                }
+        let markup = MARKUP_RE.captures_iter(unescaped).collect::<Vec<_>>();
+        let mut html = String::new();
+        let mut last = 0;
+        for caps in markup {
+            if caps.name("tag").is_some() && !hashtags {
+                continue;
             }
-        });
-        let text = self.scaling_re.replace_all(&text, |caps: &Captures| {
-            let base = &caps[1];
-            let replacement = base
-                .parse()
-                .with_context(|| format!(r#"Failed to parse scaling base "{}""#, base))
-                .and_then(|_: f32| {
-                    render(
-                        &self.ctx.reg,
-                        "scaling",
-                        &json!({"base": base, "lang": self.lang}),
-                    )
-                });
-            match replacement {
-                Ok(t) => t,
+
+            let whole = caps.get(0).expect("capture group 0 must always exist");
+            let literal = &unescaped[last..whole.start()];
+            html.push_str(&html_escape(literal));
+            self.text.push_str(literal);
+            last = whole.end();
+
+            let span = offset.map_or(self.range.clone(), |offset| {
+                offset + whole.start()..offset + whole.end()
+            });
+            let first_servings = self.servings.is_none();
+            match self.replace_markup(&caps) {
+                Ok(replacement) => {
+                    self.lint_markup(&caps, span, first_servings);
+                    html.push_str(&replacement);
+                }
                 Err(err) => {
-                    Self::print_error(err, self.path, self.ctx);
-                    caps[0].to_owned()
+                    self.diagnostics.push((Diagnostic::new(err), span));
+                    html.push_str(&html_escape(whole.as_str()));
+                    self.text.push_str(whole.as_str());
                 }
             }
-        });
-        Ok(text.to_string())
+        }
+        html.push_str(&html_escape(&unescaped[last..]));
+        self.text.push_str(&unescaped[last..]);
+        html
     }
 
-    fn replace_fallback(&mut self, unescaped: &str) -> Option<String> {
-        match self.replace(unescaped) {
-            Ok(u) => Some(u),
-            Err(err) => {
-                Self::print_error(err, self.path, self.ctx);
-                None
+    /// Check the order of the servings and the quantities scaled with them.
+    fn lint_markup(&mut self, caps: &Captures, span: Range<usize>, first_servings: bool) {
+        if caps.name("servings").is_some() {
+            if !first_servings {
+                self.diagnostics.push((
+                    Diagnostic::from_lint(
+                        Lint::MultipleServings,
+                        "Only the first {{N servings}} can be adjusted",
+                    ),
+                    span.clone(),
+                ));
+            } else if let Some(quantity) = &self.quantity {
+                self.diagnostics.push((
+                    Diagnostic::from_lint(
+                        Lint::QuantityBeforeServings,
+                        "Quantity precedes the {{N servings}} it is scaled with",
+                    ),
+                    quantity.clone(),
+                ));
             }
         }
+
+        let scalable = caps.name("scaling").is_some()
+            || caps
+                .name("quantity")
+                .is_some_and(|q| !q.as_str().trim().is_empty());
+        if scalable && self.quantity.is_none() {
+            self.quantity = Some(span);
+        }
     }
 
-    fn print_error(mut err: Error, path: &Path, ctx: &mut Ctx) {
-        err = err.context(format!(
-            "Skipping parsing error in {}",
-            path.to_string_lossy()
-        ));
+    fn replace_markup(&mut self, caps: &Captures) -> Result<String> {
+        if let Some(servings) = caps.name("servings") {
+            let servings = servings.as_str();
+            let base = servings
+                .parse::<f32>()
+                .with_context(|| format!(r#"Failed to parse servings {}"#, servings))?;
+            self.servings.get_or_insert(base);
+            self.section_ingredients = true;
+            let value = self.scale.map_or(base, |s| s.target);
+            render(
+                self.reg,
+                "servings",
+                &json!({
+                    "servings": servings,
+                    "value": value.to_string(),
+                    "prerendered": self.servings_pages(base, value),
+                    "standalone": self.standalone,
+                    "lang": self.lang,
+                }),
+            )
+        } else if let Some(scaling) = caps.name("scaling") {
+            let quantity = scaling.as_str().parse::<Quantity>()?;
+            let html = self.render_quantity(&quantity)?;
+            self.text.push_str(&quantity.to_string());
+            Ok(html)
+        } else if let Some(name) = caps.name("ingredient") {
+            let quantity = caps.name("quantity").map_or("", |q| q.as_str()).trim();
+            let ingredient = Ingredient {
+                name: name.as_str().trim().to_string(),
+                quantity: if quantity.is_empty() {
+                    None
+                } else {
+                    Some(quantity.parse()?)
+                },
+                note: caps.name("note").map(|n| n.as_str().trim().to_string()),
+            };
+            let scaling = match &ingredient.quantity {
+                Some(quantity) => Some(self.render_quantity(quantity)?),
+                None => None,
+            };
+            let html = render(
+                self.reg,
+                "ingredient",
+                &json!({
+                    "name": ingredient.name,
+                    "quantity": ingredient.quantity,
+                    "note": ingredient.note,
+                    "scaling": scaling,
+                    "lang": self.lang,
+                }),
+            )?;
+            self.text.push_str(&ingredient.to_string());
+            self.ingredients.push(ingredient);
+            self.section_ingredients = true;
+            Ok(html)
+        } else {
+            let tag = &caps["tag"];
+            let html = render(
+                self.reg,
+                "hashtag",
+                &json!({
+                    "tag": tag,
+                    "link": (!self.standalone)
+                        .then(|| self.root.to_string() + &tag_page(tag, self.lang)),
+                    "lang": self.lang,
+                }),
+            )?;
+            let space = &caps["space"];
+            self.text.push_str(space);
+            self.text.push_str(tag);
+            self.tags.push(tag.to_owned());
+            Ok(format!("{}{html}", html_escape(space)))
+        }
+    }
+
+    /// Links to the pre-rendered pages of this recipe.
+    fn servings_pages(&self, base: f32, value: f32) -> Vec<ServingsPage> {
+        if self.prerender.is_empty() {
+            return Vec::new();
+        }
 
-        ctx.print_error(err);
+        let mut servings = self.prerender.to_vec();
+        servings.push(base);
+        servings.sort_unstable_by(f32::total_cmp);
+        servings.dedup();
+        servings
+            .into_iter()
+            .map(|servings| ServingsPage {
+                servings: servings.to_string(),
+                link: if servings == base {
+                    self.stem.to_string() + ".html"
+                } else {
+                    servings_page(self.stem, servings)
+                },
+                current: servings == value,
+            })
+            .collect()
+    }
+
+    fn render_quantity(&self, quantity: &Quantity) -> Result<String> {
+        let text = match self.scale {
+            Some(scale) => quantity.scaled(scale.target / scale.base),
+            // Rounding applies to the base amount, too.
+            None if quantity.scaling.rounding.is_some() => quantity.scaled(1.),
+            None => quantity.text.clone(),
+        };
+        render(
+            self.reg,
+            "scaling",
+            &json!({
+                "base": quantity.amount.to_string(),
+                "max": quantity.max.map(|max| max.to_string()),
+                "text": text,
+                "fraction": quantity.fraction,
+                "scaling": quantity.scaling,
+                "unit": quantity.unit,
+                "symbol": quantity.unit.as_deref().and_then(lookup).map(|u| u.symbol),
+                "lang": self.lang,
+            }),
+        )
     }
 }
 
-impl<'l, I> Iterator for ServingWrapper<'l, '_, I>
-where
-    I: Iterator<Item = Event<'l>> + 'l,
-{
+impl<'l> Iterator for ServingWrapper<'l, '_> {
     type Item = Event<'l>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        Some(match self.iter.next()? {
-            Event::Text(text) => 'b: {
-                let Some(replaced) = self.replace_fallback(&text) else {
-                    break 'b Event::Text(text);
-                };
-
-                Event::Html(replaced.into())
-            }
-            Event::Code(code) => 'b: {
-                let Some(replaced) = self.replace_fallback(&code) else {
-                    break 'b Event::Code(code);
-                };
-
-                Event::Html(format!("<code>{}</code>", replaced).into())
-            }
-            e if matches!(
-                e,
-                Event::Start(Tag::Heading {
-                    level: HeadingLevel::H1,
-                    ..
-                })
-            ) =>
-            {
-                self.in_title = true;
+        let Some((event, range)) = self.iter.next() else {
+            self.end_section();
+            return None;
+        };
+        self.range = range;
+        let started = std::mem::replace(&mut self.started, true);
+        let inline = matches!(
+            event,
+            Event::End(TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough | TagEnd::Link)
+        );
+        if matches!(event, Event::SoftBreak | Event::HardBreak | Event::End(_)) && !inline {
+            self.text.push(' ');
+        }
+        Some(match event {
+            Event::Start(Tag::MetadataBlock(kind)) => {
+                if started {
+                    self.diagnostics.push((
+                        Diagnostic::new(anyhow!("Front matter must be at the start of the file")),
+                        self.range.clone(),
+                    ));
+                } else {
+                    self.front_matter = Some((kind, String::new()));
+                }
+                self.in_meta = true;
+                Event::Start(Tag::MetadataBlock(kind))
+            }
+            e @ Event::End(TagEnd::MetadataBlock(_)) => {
+                self.in_meta = false;
+                if let Some((kind, text)) = self.front_matter.take() {
+                    match parse_meta(kind, &text) {
+                        Ok(meta) => self.meta = meta,
+                        Err(err) => self
+                            .diagnostics
+                            .push((Diagnostic::new(err), self.range.clone())),
+                    }
+                }
+                e
+            }
+            Event::Text(text) if self.in_meta => {
+                if let Some((_, front_matter)) = &mut self.front_matter {
+                    front_matter.push_str(&text);
+                }
+                Event::Text(text)
+            }
+            Event::Text(text) => {
+                let verbatim = self.source.get(self.range.clone()) == Some(&text);
+                let offset = verbatim.then_some(self.range.start);
+                Event::Html(self.replace(&text, true, offset).into())
+            }
+            Event::Code(code) => {
+                // The range includes the backticks.
+                let offset = self.source[self.range.clone()]
+                    .find(&*code)
+                    .map(|i| self.range.start + i);
+                Event::Html(format!("<code>{}</code>", self.replace(&code, false, offset)).into())
+            }
+            e @ Event::Start(Tag::Heading { level, .. }) => {
+                self.end_section();
+                self.start_section(level);
+                self.in_title = level == HeadingLevel::H1;
                 e
             }
             e if matches!(e, Event::End(TagEnd::Heading(HeadingLevel::H1))) => {
                 self.in_title = false;
                 e
             }
+            e @ Event::Start(Tag::Paragraph | Tag::Item) => {
+                self.start_block();
+                e
+            }
+            e @ Event::End(TagEnd::Paragraph | TagEnd::Item) => {
+                self.end_block();
+                e
+            }
+            e @ Event::Start(Tag::CodeBlock(_)) => {
+                self.in_code_block = true;
+                e
+            }
+            e @ Event::End(TagEnd::CodeBlock) => {
+                self.in_code_block = false;
+                e
+            }
+            e @ Event::Start(Tag::Link { .. }) => {
+                self.in_link += 1;
+                e
+            }
+            e @ Event::End(TagEnd::Link) => {
+                self.in_link -= 1;
+                e
+            }
+            Event::Html(html) | Event::InlineHtml(html) if self.raw_html == RawHtml::Escaped => {
+                Event::Text(html)
+            }
+            Event::Html(html) if self.raw_html == RawHtml::Xhtml => {
+                Event::Html(to_xhtml(&html).into())
+            }
+            Event::InlineHtml(html) if self.raw_html == RawHtml::Xhtml => {
+                Event::InlineHtml(to_xhtml(&html).into())
+            }
             e => e,
         })
     }
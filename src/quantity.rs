@@ -0,0 +1,389 @@
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::{bail, Context, Error, Result};
+use serde::Serialize;
+
+use crate::units::{lookup, Dimension};
+
+/// A scalable amount with an optional unit like `12 g`.
+#[derive(Clone, Serialize)]
+#[non_exhaustive]
+pub struct Quantity {
+    /// Amount for the base number of servings.
+    pub amount: f32,
+    /// Upper bound if the amount is a range like `2-3`.
+    pub max: Option<f32>,
+    /// Amount as written in the recipe.
+    pub text: String,
+    /// Whether the amount was written as a fraction like `1 1/2` or `½`.
+    pub fraction: bool,
+    pub unit: Option<String>,
+    /// Modifiers changing how the amount scales.
+    pub scaling: Scaling,
+}
+
+/// Non-linear scaling given with modifiers like `{{2 | round}}`.
+#[derive(Clone, Default, Serialize)]
+#[non_exhaustive]
+pub struct Scaling {
+    /// Never scale the amount.
+    pub fixed: bool,
+    /// Scale with the factor raised to this power.
+    pub pow: Option<f64>,
+    pub rounding: Option<Rounding>,
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum Rounding {
+    Round,
+    Ceil,
+    Floor,
+}
+
+const VULGAR_FRACTIONS: &[(char, f32)] = &[
+    ('½', 1. / 2.),
+    ('⅓', 1. / 3.),
+    ('⅔', 2. / 3.),
+    ('¼', 1. / 4.),
+    ('¾', 3. / 4.),
+    ('⅕', 1. / 5.),
+    ('⅖', 2. / 5.),
+    ('⅗', 3. / 5.),
+    ('⅘', 4. / 5.),
+    ('⅙', 1. / 6.),
+    ('⅚', 5. / 6.),
+    ('⅛', 1. / 8.),
+    ('⅜', 3. / 8.),
+    ('⅝', 5. / 8.),
+    ('⅞', 7. / 8.),
+];
+
+impl FromStr for Quantity {
+    type Err = Error;
+
+    /// Parse `<amount>[ <unit>]` or `<amount>[%<unit>]`.
+    ///
+    /// The amount can also be a range `<amount>-<amount>`.
+    /// Modifiers can follow, each separated by `|`.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut modifiers = s.split('|');
+        let s = modifiers.next().unwrap_or_default().trim();
+        let (text, unit) = match s.split_once('%') {
+            Some((text, unit)) => (text.trim(), unit.trim()),
+            None => split_amount(s),
+        };
+
+        let (amount, max, fraction) = parse_range(text)
+            .with_context(|| format!(r#"Failed to parse scaling base "{}""#, text))?;
+        if max.is_some_and(|max| max < amount) {
+            bail!(r#"Lower bound of range "{text}" is greater than its upper bound"#);
+        }
+
+        let mut scaling = Scaling::default();
+        for modifier in modifiers {
+            scaling.apply(modifier.trim())?;
+        }
+
+        Ok(Self {
+            amount,
+            max,
+            text: text.to_string(),
+            fraction,
+            unit: Some(unit).filter(|u| !u.is_empty()).map(str::to_string),
+            scaling,
+        })
+    }
+}
+
+impl Quantity {
+    /// Amount scaled by the factor and formatted like `rumtopf.js` does.
+    pub(crate) fn scaled(&self, factor: f32) -> String {
+        let temperature = self
+            .unit
+            .as_deref()
+            .and_then(lookup)
+            .is_some_and(|u| u.dimension == Dimension::Temperature);
+        let scale = match self.scaling.pow {
+            _ if self.scaling.fixed || temperature => 1.,
+            Some(pow) => (factor as f64).powf(pow),
+            None => factor as f64,
+        };
+
+        [Some(self.amount), self.max]
+            .into_iter()
+            .flatten()
+            .map(|value| {
+                let mut value = value as f64 * scale;
+                value = match self.scaling.rounding {
+                    Some(Rounding::Round) => value.round(),
+                    Some(Rounding::Ceil) => value.ceil(),
+                    Some(Rounding::Floor) => value.floor(),
+                    None => value,
+                };
+                if self.fraction {
+                    format_fraction(value)
+                } else {
+                    format_decimal(value)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("–")
+    }
+}
+
+impl Scaling {
+    fn apply(&mut self, modifier: &str) -> Result<()> {
+        let (name, arg) = modifier
+            .split_once(char::is_whitespace)
+            .map_or((modifier, ""), |(name, arg)| (name, arg.trim()));
+        if name != "pow" && !arg.is_empty() {
+            bail!(r#"Scaling modifier "{name}" does not take an argument"#);
+        }
+
+        match name {
+            "fixed" => self.fixed = true,
+            "pow" => {
+                let exponent: f64 = arg
+                    .parse()
+                    .with_context(|| format!(r#"Failed to parse exponent "{arg}""#))?;
+                // `rumtopf.js` cannot reproduce scaling by non-finite powers.
+                if !exponent.is_finite() {
+                    bail!(r#"Exponent "{arg}" is not finite"#);
+                }
+                self.pow = Some(exponent);
+            }
+            "round" => self.rounding = Some(Rounding::Round),
+            "ceil" => self.rounding = Some(Rounding::Ceil),
+            "floor" => self.rounding = Some(Rounding::Floor),
+            _ => bail!(r#"Unknown scaling modifier "{name}""#),
+        }
+        Ok(())
+    }
+}
+
+const FRACTIONS: &[(f64, &str)] = &[
+    (1. / 8., "⅛"),
+    (1. / 4., "¼"),
+    (1. / 3., "⅓"),
+    (3. / 8., "⅜"),
+    (1. / 2., "½"),
+    (5. / 8., "⅝"),
+    (2. / 3., "⅔"),
+    (3. / 4., "¾"),
+    (7. / 8., "⅞"),
+];
+
+fn format_decimal(n: f64) -> String {
+    let rounded = format!("{:.2}", n);
+    rounded
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+/// Format a number as the nearest whole number plus a vulgar fraction.
+fn format_fraction(n: f64) -> String {
+    // Too small for any sensible fraction.
+    if n < FRACTIONS[0].0 / 2. {
+        return format_decimal(n);
+    }
+
+    let mut whole = n.floor();
+    let rest = n - whole;
+    let (mut distance, mut fraction) = (rest, "");
+    for &(value, symbol) in FRACTIONS {
+        if (rest - value).abs() < distance {
+            (distance, fraction) = ((rest - value).abs(), symbol);
+        }
+    }
+    if 1. - rest < distance {
+        (whole, fraction) = (whole + 1., "");
+    }
+
+    if whole == 0. && !fraction.is_empty() {
+        return fraction.to_string();
+    }
+    format_decimal(whole) + fraction
+}
+
+/// Split the amount from the unit.
+///
+/// Amounts like `1 1/2` or `2 - 3` contain whitespace, so the longest sequence
+/// of words forming an amount is taken.
+fn split_amount(s: &str) -> (&str, &str) {
+    let end = s
+        .match_indices(char::is_whitespace)
+        .map(|(i, _)| i)
+        .chain([s.len()])
+        .rev()
+        .find(|&i| parse_range(s[..i].trim_end()).is_some())
+        .or_else(|| s.find(char::is_whitespace))
+        .unwrap_or(s.len());
+    (s[..end].trim_end(), s[end..].trim())
+}
+
+/// Parse an amount or a range of amounts.
+///
+/// Returns the lower bound, the upper bound of ranges, and whether a fraction
+/// was used.
+fn parse_range(text: &str) -> Option<(f32, Option<f32>, bool)> {
+    if let Some((amount, fraction)) = parse_amount(text) {
+        return Some((amount, None, fraction));
+    }
+
+    text.match_indices(['-', '–']).find_map(|(i, dash)| {
+        let (min, min_fraction) = parse_amount(text[..i].trim_end())?;
+        let (max, max_fraction) = parse_amount(text[i + dash.len()..].trim_start())?;
+        Some((min, Some(max), min_fraction || max_fraction))
+    })
+}
+
+/// Parse a decimal number, a fraction, or a mixed number.
+///
+/// Also returns whether the amount was written as a fraction.
+fn parse_amount(text: &str) -> Option<(f32, bool)> {
+    if let Ok(amount) = text.parse() {
+        return Some((amount, false));
+    }
+
+    let (whole, fraction) = match text.rsplit_once(char::is_whitespace) {
+        Some((whole, fraction)) => (whole.trim_end(), fraction),
+        None if text.contains(['/', '⁄']) => ("", text),
+        // Vulgar fractions may directly follow the whole number like in `1½`.
+        None => text.split_at(text.char_indices().last()?.0),
+    };
+    let whole = if whole.is_empty() {
+        0
+    } else {
+        whole.parse::<u32>().ok()?
+    };
+    Some((whole as f32 + parse_fraction(fraction)?, true))
+}
+
+fn parse_fraction(text: &str) -> Option<f32> {
+    let mut chars = text.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if let Some(&(_, value)) = VULGAR_FRACTIONS.iter().find(|&&(v, _)| v == c) {
+            return Some(value);
+        }
+    }
+
+    let (numerator, denominator) = text.split_once(['/', '⁄'])?;
+    let numerator = numerator.parse::<u32>().ok()?;
+    let denominator = denominator.parse::<u32>().ok()?;
+    (denominator != 0).then(|| numerator as f32 / denominator as f32)
+}
+
+/// The amount is formatted like on the pages, for example `3e2 g` as `300 g`.
+impl Display for Quantity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.scaled(1.))?;
+        if let Some(unit) = &self.unit {
+            write!(f, " {unit}")?;
+        }
+        Ok(())
+    }
+}
+
+/// An ingredient written as `@name{quantity}(note)`.
+#[derive(Clone, Serialize)]
+#[non_exhaustive]
+pub struct Ingredient {
+    pub name: String,
+    pub quantity: Option<Quantity>,
+    pub note: Option<String>,
+}
+
+impl Display for Ingredient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(quantity) = &self.quantity {
+            write!(f, "{quantity} ")?;
+        }
+        write!(f, "{}", self.name)?;
+        if let Some(note) = &self.note {
+            write!(f, " ({note})")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Quantity {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn amount_and_unit() {
+        let quantity = parse("12 g");
+        assert_eq!((quantity.amount, quantity.max), (12., None));
+        assert_eq!(quantity.unit.as_deref(), Some("g"));
+        assert!(!quantity.fraction);
+
+        let quantity = parse("3e2%g");
+        assert_eq!(quantity.amount, 300.);
+        assert_eq!(quantity.to_string(), "300 g");
+
+        let quantity = parse("2 large cups");
+        assert_eq!(quantity.amount, 2.);
+        assert_eq!(quantity.unit.as_deref(), Some("large cups"));
+        assert_eq!(parse("3").unit, None);
+        assert!("abc".parse::<Quantity>().is_err());
+    }
+
+    #[test]
+    fn fractions() {
+        for (text, amount) in [("1/2", 0.5), ("1 1/2", 1.5), ("1½", 1.5), ("¾", 0.75)] {
+            let quantity = parse(text);
+            assert_eq!(quantity.amount, amount, "{text}");
+            assert!(quantity.fraction, "{text}");
+        }
+        assert_eq!(parse("1 1/2 cups").unit.as_deref(), Some("cups"));
+        assert!("1/0".parse::<Quantity>().is_err());
+
+        assert_eq!(parse("1/2").scaled(3.), "1½");
+        assert_eq!(parse("1/3").scaled(2.), "⅔");
+        assert_eq!(parse("1/2").scaled(0.1), "0.05");
+        assert_eq!(parse("0.5").scaled(3.), "1.5");
+    }
+
+    #[test]
+    fn ranges() {
+        let quantity = parse("2-3 eggs");
+        assert_eq!((quantity.amount, quantity.max), (2., Some(3.)));
+        assert_eq!(quantity.unit.as_deref(), Some("eggs"));
+        assert_eq!(quantity.scaled(2.), "4–6");
+
+        let quantity = parse("1/2 – 1");
+        assert_eq!((quantity.amount, quantity.max), (0.5, Some(1.)));
+        assert!(quantity.fraction);
+
+        let err = "3-2".parse::<Quantity>().err().unwrap();
+        assert!(err.to_string().contains("greater than its upper bound"));
+    }
+
+    #[test]
+    fn modifiers() {
+        assert_eq!(parse("5 | round").scaled(0.5), "3");
+        assert_eq!(parse("5 | ceil").scaled(0.3), "2");
+        assert_eq!(parse("5 | floor").scaled(0.5), "2");
+        assert_eq!(parse("5 | fixed").scaled(4.), "5");
+        assert_eq!(parse("4 | pow 0.5").scaled(4.), "8");
+        assert_eq!(parse("4 | pow 0").scaled(4.), "4");
+        assert_eq!(parse("5 g | pow 0.8 | round").scaled(2.), "9");
+        assert_eq!(parse("1.4 | round").scaled(1.), "1");
+        // Temperatures are never scaled.
+        assert_eq!(parse("180 °C").scaled(2.), "180");
+
+        for modifier in ["pow NaN", "pow inf", "pow", "round 2", "double"] {
+            assert!(
+                format!("1 | {modifier}").parse::<Quantity>().is_err(),
+                "{modifier}"
+            );
+        }
+    }
+}
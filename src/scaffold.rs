@@ -0,0 +1,179 @@
+use std::{
+    fs::{read_dir, read_to_string, File},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{bail, Context, Result};
+use handlebars::{no_escape, Handlebars};
+use serde_json::json;
+
+use crate::{
+    diagnostics::{Diagnostics, Report},
+    l10n::L10nHelper,
+    utils::slugify,
+};
+
+/// Built-in template of new recipes.
+const SKELETON: &str = include_str!("skeleton.md");
+
+/// Language of the built-in skeleton when no language is given.
+const FALLBACK_LANG: &str = "en";
+
+/// Builder of a new recipe created from a skeleton.
+///
+/// The file name is derived from the title and the language.
+pub struct NewRecipe {
+    dir: PathBuf,
+    title: String,
+    lang: Option<String>,
+    skeleton: Option<PathBuf>,
+    l10n: Option<PathBuf>,
+}
+
+impl NewRecipe {
+    /// Start building a recipe with the title in the source directory.
+    pub fn new(dir: impl Into<PathBuf>, title: impl Into<String>) -> Self {
+        Self {
+            dir: dir.into(),
+            title: title.into(),
+            lang: None,
+            skeleton: None,
+            l10n: None,
+        }
+    }
+
+    /// Language of the recipe, which is added to the file name.
+    pub fn lang(mut self, lang: impl Into<String>) -> Self {
+        self.lang = Some(lang.into());
+        self
+    }
+
+    /// Handlebars template replacing the built-in skeleton, in which
+    /// `{{title}}` is the title and `{{l10n "key"}}` a localized text.
+    pub fn skeleton(mut self, file: impl Into<PathBuf>) -> Self {
+        self.skeleton = Some(file.into());
+        self
+    }
+
+    /// Custom localization file to override the built-in one.
+    pub fn l10n(mut self, file: impl Into<PathBuf>) -> Self {
+        self.l10n = Some(file.into());
+        self
+    }
+
+    /// Create the recipe and return its path.
+    ///
+    /// Fails if a recipe with the same file name exists in any category.
+    pub fn create(self) -> Result<(PathBuf, Report)> {
+        let (dir, title, lang) = (&self.dir, &self.title, self.lang.as_deref());
+        let short = slugify(title);
+        if short.is_empty() {
+            bail!("Title \"{title}\" contains no letters or digits");
+        }
+        let stem = match lang {
+            Some(lang) => format!("{short}.{lang}"),
+            None => short,
+        };
+        check_collision(dir, &stem)?;
+
+        let diagnostics = Arc::new(Diagnostics::default());
+        let skeleton = match &self.skeleton {
+            Some(path) => read_to_string(path)
+                .with_context(|| format!("Failed to read skeleton {}", path.to_string_lossy()))?,
+            None => SKELETON.to_string(),
+        };
+        let mut reg = Handlebars::new();
+        reg.set_strict_mode(true);
+        reg.register_escape_fn(no_escape);
+        reg.register_helper(
+            "l10n",
+            Box::new(L10nHelper::new(
+                self.l10n.clone(),
+                FALLBACK_LANG.to_string(),
+                diagnostics.clone(),
+            )?),
+        );
+        let recipe = reg
+            .render_template(
+                &skeleton,
+                &json!({
+                    "title": title,
+                    "lang": lang.unwrap_or(FALLBACK_LANG),
+                }),
+            )
+            .context("Failed to render skeleton")?;
+
+        let path = dir.join(stem + ".md");
+        let mut file = File::create_new(&path)
+            .with_context(|| format!("Failed to create recipe {}", path.to_string_lossy()))?;
+        file.write_all(recipe.as_bytes())
+            .with_context(|| format!("Failed to write recipe {}", path.to_string_lossy()))?;
+        Ok((path, diagnostics.take()))
+    }
+}
+
+/// Refuse stems of existing recipes, also if they only differ by case.
+///
+/// Subdirectories are categories of the same website and therefore checked,
+/// too, except for the ones skipped by the generation like `.git`.
+fn check_collision(dir: &Path, stem: &str) -> Result<()> {
+    let entries = read_dir(dir)
+        .with_context(|| format!("Failed to read source directory {}", dir.to_string_lossy()))?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            if !entry.file_name().to_string_lossy().starts_with('.') {
+                check_collision(&path, stem)?;
+            }
+            continue;
+        }
+        let is_recipe = path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("md"));
+        let existing = path.file_stem().map(|s| s.to_string_lossy());
+        if let Some(existing) =
+            existing.filter(|s| is_recipe && s.to_lowercase() == stem.to_lowercase())
+        {
+            bail!(
+                "Recipe {existing} already exists in {}",
+                dir.to_string_lossy()
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env::temp_dir,
+        fs::{create_dir_all, remove_dir_all, write},
+        process,
+    };
+
+    use super::*;
+
+    #[test]
+    fn collisions() {
+        let dir = temp_dir().join(format!("rumtopf-test-{}-collision", process::id()));
+        let _ = remove_dir_all(&dir);
+        create_dir_all(dir.join("baking/bread")).unwrap();
+        create_dir_all(dir.join(".git")).unwrap();
+        write(dir.join("pizza.md"), "").unwrap();
+        write(dir.join("baking/bread/Apple_Pie.de.md"), "").unwrap();
+        write(dir.join("baking/notes.txt"), "").unwrap();
+        write(dir.join(".git/tarte.md"), "").unwrap();
+
+        assert!(check_collision(&dir, "pizza").is_err());
+        // Recipes in categories and differing only by case also collide.
+        let err = check_collision(&dir, "apple_pie.de").unwrap_err();
+        assert!(err.to_string().contains("bread"), "{err}");
+        assert!(check_collision(&dir, "apple_pie").is_ok());
+        assert!(check_collision(&dir, "notes").is_ok());
+        assert!(check_collision(&dir, "tarte").is_ok());
+
+        remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,305 @@
+use std::{
+    env::temp_dir,
+    fs::{canonicalize, metadata, read, read_dir},
+    io::{BufRead, BufReader, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    path::{Component, Path, PathBuf},
+    process::{self, ExitCode},
+    sync::{Arc, PoisonError, RwLock},
+    thread::{sleep, spawn},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Context, Result};
+
+use rumtopf::percent_decode;
+
+use crate::{args::ServeArgs, check_previous, generate, print_error, remove_dest, Options};
+
+/// Path polled by the reload snippet for the current generation.
+const GENERATION_PATH: &str = "/__rumtopf/generation";
+
+/// Snippet injected into served pages to reload them after regenerating.
+///
+/// It is only added to responses of the server and never written to files.
+const RELOAD: &str = r#"<script>
+(() => {
+  let generation = null;
+  setInterval(async () => {
+    try {
+      const response = await fetch("/__rumtopf/generation");
+      const current = await response.text();
+      if (generation !== null && current !== generation) location.reload();
+      generation = current;
+    } catch (_) {}
+  }, 1000);
+})();
+</script>
+"#;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Number of finished generations, locked for writing while generating.
+type Generation = Arc<RwLock<u64>>;
+
+/// Modification times and sizes of all watched files.
+type Snapshot = Vec<(PathBuf, Option<SystemTime>, u64)>;
+
+pub(crate) fn serve(args: ServeArgs) -> Result<ExitCode> {
+    let temporary = args.destination.is_none();
+    let dest = args
+        .destination
+        .unwrap_or_else(|| temp_dir().join(format!("rumtopf-{}", process::id())));
+    // Generating into the source would trigger the next generation.
+    check_dest(&args.source, &dest)?;
+    let watched = [
+        Some(args.source.clone()),
+        args.options.templates.clone(),
+        args.options.l10n.clone(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>();
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, args.port))
+        .with_context(|| format!("Failed to listen on port {}", args.port))?;
+    let generation = Generation::default();
+    let mut snapshot = take_snapshot(&watched);
+    if temporary {
+        // Only the temporary directory created by the server is removed.
+        remove_dest(&dest)?;
+        let dest = dest.clone();
+        ctrlc::set_handler(move || {
+            if let Err(err) = remove_dest(&dest) {
+                eprintln!("{err:#}");
+            }
+            process::exit(0);
+        })
+        .context("Failed to set handler for removing the temporary directory")?;
+    }
+    regenerate(&args.source, &dest, &args.options);
+    eprintln!(
+        "Serving {} at http://localhost:{}/",
+        dest.to_string_lossy(),
+        args.port
+    );
+
+    {
+        let dest = dest.clone();
+        let generation = generation.clone();
+        spawn(move || listen(listener, &dest, &generation));
+    }
+
+    loop {
+        sleep(POLL_INTERVAL);
+        let current = take_snapshot(&watched);
+        if current == snapshot {
+            continue;
+        }
+        snapshot = current;
+
+        let mut generation = generation.write().unwrap_or_else(PoisonError::into_inner);
+        eprintln!("Sources changed, regenerating");
+        regenerate(&args.source, &dest, &args.options);
+        *generation += 1;
+    }
+}
+
+/// Refuse destinations overlapping with the source or containing other files.
+fn check_dest(src: &Path, dest: &Path) -> Result<()> {
+    let src = canonicalize(src)
+        .with_context(|| format!("Failed to resolve source {}", src.to_string_lossy()))?;
+    let resolved = resolve(dest).with_context(|| {
+        format!(
+            "Failed to resolve destination directory {}",
+            dest.to_string_lossy()
+        )
+    })?;
+    if resolved.starts_with(&src) || src.starts_with(&resolved) {
+        bail!("Destination and source directory must not contain each other");
+    }
+    if dest.exists() {
+        check_previous(dest)?;
+    }
+    Ok(())
+}
+
+/// Canonicalize a path, which may not exist yet.
+fn resolve(path: &Path) -> std::io::Result<PathBuf> {
+    if path.exists() {
+        return canonicalize(path);
+    }
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => resolve(parent)?,
+        _ => canonicalize(".")?,
+    };
+    Ok(match path.file_name() {
+        Some(name) => parent.join(name),
+        None => parent,
+    })
+}
+
+fn regenerate(src: &Path, dest: &Path, options: &Options) {
+    let result = generate(src, dest, options, true);
+    match result {
+        Ok(false) => eprintln!("Website generated"),
+        Ok(true) => eprintln!("Website generated with errors"),
+        Err(err) => print_error(&err, options.message_format),
+    }
+}
+
+fn take_snapshot(paths: &[PathBuf]) -> Snapshot {
+    let mut snapshot = Snapshot::new();
+    for path in paths {
+        add_to_snapshot(&mut snapshot, path);
+    }
+    snapshot
+}
+
+fn add_to_snapshot(snapshot: &mut Snapshot, path: &Path) {
+    let Ok(meta) = metadata(path) else {
+        snapshot.push((path.to_path_buf(), None, 0));
+        return;
+    };
+    snapshot.push((path.to_path_buf(), meta.modified().ok(), meta.len()));
+
+    if meta.is_dir() {
+        let Ok(dir) = read_dir(path) else {
+            return;
+        };
+        let mut entries = dir.flatten().map(|e| e.path()).collect::<Vec<_>>();
+        entries.sort_unstable();
+        for entry in entries {
+            add_to_snapshot(snapshot, &entry);
+        }
+    }
+}
+
+fn listen(listener: TcpListener, dest: &Path, generation: &Generation) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("Failed to accept connection: {err}");
+                continue;
+            }
+        };
+        let dest = dest.to_path_buf();
+        let generation = generation.clone();
+        spawn(move || {
+            if let Err(err) = handle(stream, &dest, &generation) {
+                eprintln!("{err:#}");
+            }
+        });
+    }
+}
+
+fn handle(mut stream: TcpStream, dest: &Path, generation: &Generation) -> Result<()> {
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader
+        .read_line(&mut request)
+        .context("Failed to read request")?;
+    // Skip the headers.
+    let mut header = String::new();
+    while reader
+        .read_line(&mut header)
+        .context("Failed to read request")?
+        > 2
+    {
+        header.clear();
+    }
+
+    let mut parts = request.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or("/"));
+    let head = method == "HEAD";
+    if method != "GET" && !head {
+        return respond(
+            &mut stream,
+            "405 Method Not Allowed",
+            "text/plain",
+            b"",
+            head,
+        );
+    }
+    let path = percent_decode(target.split(['?', '#']).next().unwrap_or("/"));
+
+    let generation = generation.read().unwrap_or_else(PoisonError::into_inner);
+    if path == GENERATION_PATH {
+        let body = generation.to_string();
+        return respond(&mut stream, "200 OK", "text/plain", body.as_bytes(), head);
+    }
+
+    let relative = Path::new(path.trim_start_matches('/'));
+    if !relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+    {
+        return respond(&mut stream, "404 Not Found", "text/plain", b"", head);
+    }
+    let mut file = dest.join(relative);
+    if file.is_dir() {
+        if !path.ends_with('/') {
+            // Relative links only work with the trailing slash.
+            let response = format!(
+                "HTTP/1.1 301 Moved Permanently\r\nLocation: {path}/\r\n\
+                 Content-Length: 0\r\nConnection: close\r\n\r\n"
+            );
+            return stream
+                .write_all(response.as_bytes())
+                .context("Failed to write response");
+        }
+        file.push("index.html");
+    }
+
+    let Ok(mut body) = read(&file) else {
+        return respond(&mut stream, "404 Not Found", "text/plain", b"", head);
+    };
+    let content_type = content_type(&file);
+    if content_type.starts_with("text/html") {
+        let html = String::from_utf8_lossy(&body);
+        let end = html.rfind("</body>").unwrap_or(html.len());
+        body = format!("{}{RELOAD}{}", &html[..end], &html[end..]).into_bytes();
+    }
+    respond(&mut stream, "200 OK", content_type, &body, head)
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+    head: bool,
+) -> Result<()> {
+    let header = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\n\
+         Content-Length: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    stream
+        .write_all(header.as_bytes())
+        .and_then(|_| if head { Ok(()) } else { stream.write_all(body) })
+        .context("Failed to write response")
+}
+
+fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("xml") => "application/xml",
+        Some("txt" | "md") => "text/plain; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("ico") => "image/x-icon",
+        Some("pdf") => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
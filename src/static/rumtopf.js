@@ -1,13 +1,95 @@
 "use strict";
 
+const UNITS_KEY = "rumtopf_units";
+
+const FRACTIONS = [
+  [1 / 8, "⅛"],
+  [1 / 4, "¼"],
+  [1 / 3, "⅓"],
+  [3 / 8, "⅜"],
+  [1 / 2, "½"],
+  [5 / 8, "⅝"],
+  [2 / 3, "⅔"],
+  [3 / 4, "¾"],
+  [7 / 8, "⅞"],
+];
+
+const ROUNDINGS = {
+  round: Math.round,
+  ceil: Math.ceil,
+  floor: Math.floor,
+};
+
 function round(n) {
   return parseFloat(n.toFixed(2));
 }
 
+function format_decimal(n) {
+  return round(n).toLocaleString(document.documentElement.lang);
+}
+
+/** Format a number as the nearest whole number plus a vulgar fraction. */
+function format_fraction(n) {
+  // Too small for any sensible fraction.
+  if (n < FRACTIONS[0][0] / 2) return format_decimal(n);
+
+  let whole = Math.floor(n);
+  let rest = n - whole;
+  let [distance, fraction] = [rest, ""];
+  for (const [value, symbol] of FRACTIONS) {
+    if (Math.abs(rest - value) < distance) {
+      [distance, fraction] = [Math.abs(rest - value), symbol];
+    }
+  }
+  if (1 - rest < distance) [whole, fraction] = [whole + 1, ""];
+
+  if (whole === 0 && fraction !== "") return fraction;
+  return format_decimal(whole) + fraction;
+}
+
+/**
+ * Convert values into the most sensible unit of the given system.
+ *
+ * Returns the converted values and the symbol of the new unit.
+ */
+function convert(values, symbol, system) {
+  const unit = UNITS[symbol];
+  if (unit === undefined || system === "original") return [values, symbol];
+
+  const bases = values.map((value) => value * unit.factor + unit.offset);
+  const candidates = Object.entries(UNITS)
+    .filter(
+      ([_, u]) =>
+        u.preferred && u.dimension === unit.dimension && u.system === system
+    )
+    .sort(([_a, a], [_b, b]) => a.factor - b.factor);
+  if (candidates.length === 0) return [values, symbol];
+
+  // Use the largest unit in which all values are still at least one.
+  let [target_symbol, target] = candidates[0];
+  for (const [s, u] of candidates) {
+    if (bases.every((base) => Math.abs((base - u.offset) / u.factor) >= 1)) {
+      [target_symbol, target] = [s, u];
+    }
+  }
+  return [
+    bases.map((base) => (base - target.offset) / target.factor),
+    target_symbol,
+  ];
+}
+
+/** Scale the values of a quantity by the factor unless they are fixed. */
+function scale(values, factor, symbol, fixed, pow) {
+  if (fixed || UNITS[symbol]?.dimension === "temperature") return values;
+  const scale = Number.isNaN(pow) ? factor : Math.pow(factor, pow);
+  return values.map((value) => value * scale);
+}
+
 function calc_recipe() {
   const inputs = document.getElementsByClassName("servings_input");
   const scalings = document.getElementsByClassName("scaling");
   const submits = document.getElementsByClassName("servings_submit");
+  const selects = document.getElementsByClassName("units_select");
 
   for (const elem of submits) {
     if (elem instanceof HTMLInputElement) elem.disabled = false;
@@ -22,16 +104,67 @@ function calc_recipe() {
   if (Number.isNaN(base)) base = 1;
   let factor = servings / base;
 
+  let system =
+    params.get("units") ?? localStorage.getItem(UNITS_KEY) ?? "original";
+
   for (const elem of scalings) {
     let base = parseFloat(elem.dataset["base"]);
     if (Number.isNaN(base)) continue;
-    elem.textContent = round(factor * base).toLocaleString(
-      document.documentElement.lang
+    let values = [base];
+    const max = parseFloat(elem.dataset["baseMax"]);
+    if (!Number.isNaN(max)) values.push(max);
+
+    let symbol = elem.dataset["unit"];
+    values = scale(
+      values,
+      factor,
+      symbol,
+      "fixed" in elem.dataset,
+      parseFloat(elem.dataset["pow"])
     );
+    [values, symbol] = convert(values, symbol, system);
+    const rounding = ROUNDINGS[elem.dataset["round"]];
+    if (rounding !== undefined) values = values.map(rounding);
+
+    // Cooks using US customary units are used to fractions.
+    const converted = symbol !== elem.dataset["unit"];
+    const fraction =
+      "fraction" in elem.dataset ||
+      (converted && UNITS[symbol]?.system === "imperial");
+    elem.textContent = values
+      .map((value) => (fraction ? format_fraction(value) : format_decimal(value)))
+      .join("–");
+
+    const unit = elem.parentElement?.querySelector(".unit");
+    if (unit instanceof HTMLElement && symbol !== undefined) {
+      unit.dataset["original"] ??= unit.textContent;
+      unit.textContent = converted ? symbol : unit.dataset["original"];
+    }
   }
   for (const elem of inputs) {
     if (elem instanceof HTMLInputElement) elem.value = servings;
   }
+  for (const elem of selects) {
+    if (!(elem instanceof HTMLSelectElement)) continue;
+    elem.disabled = false;
+    elem.value = system;
+  }
+}
+
+function change_units(event) {
+  localStorage.setItem(UNITS_KEY, event.target.value);
+
+  // The URL parameter takes precedence over the stored setting.
+  const url = new URL(document.location.href);
+  url.searchParams.delete("units");
+  history.replaceState(null, "", url);
+
+  calc_recipe();
 }
 
-window.addEventListener("DOMContentLoaded", (_) => calc_recipe());
+window.addEventListener("DOMContentLoaded", (_) => {
+  for (const elem of document.getElementsByClassName("units_select")) {
+    elem.addEventListener("change", change_units);
+  }
+  calc_recipe();
+});
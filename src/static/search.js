@@ -0,0 +1,87 @@
+"use strict";
+
+const MAX_RESULTS = 10;
+
+let search_index = null;
+
+function load_index(input) {
+  if (search_index === null) {
+    search_index = fetch(input.dataset["index"]).then((response) =>
+      response.json()
+    );
+  }
+  return search_index;
+}
+
+function score(entry, terms) {
+  const title = entry.title.toLowerCase();
+  const tags = entry.tags.map((tag) => tag.toLowerCase());
+  const text = entry.text.toLowerCase();
+
+  let score = 0;
+  for (const term of terms) {
+    if (title.includes(term)) score += 3;
+    else if (tags.some((tag) => tag.includes(term))) score += 2;
+    else if (text.includes(term)) score += 1;
+    else return 0;
+  }
+  return score;
+}
+
+async function search(input, results) {
+  const terms = input.value.toLowerCase().split(/\s+/).filter((t) => t);
+  if (terms.length === 0) {
+    results.classList.remove("show");
+    results.replaceChildren();
+    return;
+  }
+
+  let index;
+  try {
+    index = await load_index(input);
+  } catch (err) {
+    console.error("failed to load search index", err);
+    return;
+  }
+
+  const found = index
+    .map((entry) => [score(entry, terms), entry])
+    .filter(([score, _]) => score > 0)
+    .sort(([a, _a], [b, _b]) => b - a)
+    .slice(0, MAX_RESULTS);
+
+  const items = found.map(([_, entry]) => {
+    const link = document.createElement("a");
+    link.className = "dropdown-item";
+    link.href = input.dataset["root"] + entry.link;
+    link.textContent = entry.title;
+    const item = document.createElement("li");
+    item.append(link);
+    return item;
+  });
+  if (items.length === 0) {
+    const text = document.createElement("span");
+    text.className = "dropdown-item-text text-muted";
+    text.textContent = input.dataset["empty"];
+    const item = document.createElement("li");
+    item.append(text);
+    items.push(item);
+  }
+
+  results.replaceChildren(...items);
+  results.classList.add("show");
+}
+
+window.addEventListener("DOMContentLoaded", (_) => {
+  for (const input of document.getElementsByClassName("search_input")) {
+    const results = input.parentElement.querySelector(".search_results");
+    if (results === null) continue;
+
+    input.addEventListener("input", (_) => search(input, results));
+    input.addEventListener("focus", (_) => search(input, results));
+    input.parentElement.addEventListener("focusout", (event) => {
+      if (!input.parentElement.contains(event.relatedTarget))
+        results.classList.remove("show");
+    });
+  }
+});
@@ -0,0 +1,179 @@
+"use strict";
+
+const SHOPPING_KEY = "rumtopf_shopping";
+
+/** Recipes on the shopping list with their scaled ingredients. */
+function load_list() {
+  try {
+    const list = JSON.parse(localStorage.getItem(SHOPPING_KEY) ?? "[]");
+    return Array.isArray(list) ? list : [];
+  } catch (_) {
+    return [];
+  }
+}
+
+function store_list(list) {
+  localStorage.setItem(SHOPPING_KEY, JSON.stringify(list));
+}
+
+/** Add the ingredients of the recipe page at the current servings. */
+function add_recipe(button) {
+  const data = document.getElementById("shopping_ingredients");
+  if (data === null) return;
+  const ingredients = JSON.parse(data.textContent);
+
+  const input = document.getElementsByClassName("servings_input")[0];
+  const servings = parseFloat(input?.value);
+  const base = parseFloat(input?.dataset["base"]);
+  const factor = Number.isNaN(servings) || Number.isNaN(base) ? 1 : servings / base;
+
+  const items = ingredients.map((ingredient) => {
+    const item = { name: ingredient.name, note: ingredient.note };
+    const quantity = ingredient.quantity;
+    if (quantity === null) return item;
+
+    let values = [quantity.amount];
+    if (quantity.max !== null) values.push(quantity.max);
+    values = scale(
+      values,
+      factor,
+      quantity.symbol,
+      quantity.scaling.fixed,
+      quantity.scaling.pow ?? NaN
+    );
+    const rounding = ROUNDINGS[quantity.scaling.rounding];
+    if (rounding !== undefined) values = values.map(rounding);
+    [item.amount, item.max] = values;
+    item.unit = quantity.unit;
+    item.fraction = quantity.fraction;
+    return item;
+  });
+
+  // Adding a recipe again replaces it, for example with other servings or from
+  // a pre-rendered page.
+  const link = new URL(button.dataset["link"], location.href).pathname;
+  const list = load_list().filter((r) => r.link !== link);
+  list.push({
+    title: button.dataset["title"],
+    link,
+    servings: Number.isNaN(servings) ? null : servings,
+    items,
+  });
+  store_list(list);
+
+  const added = document.getElementById("shopping_added");
+  if (added !== null) added.hidden = false;
+}
+
+/**
+ * Merge the ingredients of all recipes with the same name and unit.
+ *
+ * Ingredients without a quantity are listed once.
+ */
+function merge_items(list) {
+  const merged = new Map();
+  for (const recipe of list) {
+    for (const item of recipe.items ?? []) {
+      const key = [item.name.toLowerCase(), item.unit ?? ""].join("\u0000");
+      const entry = merged.get(key);
+      if (entry === undefined) {
+        merged.set(key, { ...item, notes: item.note ? [item.note] : [] });
+        continue;
+      }
+      if (item.note && !entry.notes.includes(item.note)) {
+        entry.notes.push(item.note);
+      }
+      if (item.amount === undefined) continue;
+      if (entry.amount === undefined) {
+        Object.assign(entry, { amount: item.amount, max: item.max });
+      } else {
+        if (entry.max !== undefined || item.max !== undefined) {
+          entry.max = (entry.max ?? entry.amount) + (item.max ?? item.amount);
+        }
+        entry.amount += item.amount;
+      }
+      entry.fraction = entry.fraction && item.fraction;
+    }
+  }
+  return [...merged.values()].sort((a, b) => a.name.localeCompare(b.name));
+}
+
+function format_item(item) {
+  let text = item.name;
+  if (item.amount !== undefined) {
+    const format = item.fraction ? format_fraction : format_decimal;
+    const values = [item.amount];
+    if (item.max !== undefined) values.push(item.max);
+    const amount = values.map(format).join("–");
+    text = [amount, item.unit, item.name].filter((t) => t).join(" ");
+  }
+  if (item.notes.length > 0) text += ` (${item.notes.join(", ")})`;
+  return text;
+}
+
+/** Fill the shopping list page from the stored recipes. */
+function show_list() {
+  const items = document.getElementById("shopping_items");
+  const recipes = document.getElementById("shopping_recipes");
+  const empty = document.getElementById("shopping_empty");
+  const actions = document.getElementById("shopping_actions");
+  const list = load_list();
+
+  items.replaceChildren(
+    ...merge_items(list).map((item) => {
+      const li = document.createElement("li");
+      li.textContent = format_item(item);
+      return li;
+    })
+  );
+  recipes.replaceChildren(
+    ...list.map((recipe) => {
+      const link = document.createElement("a");
+      link.href = recipe.link;
+      link.textContent = recipe.title;
+      const remove = document.createElement("button");
+      remove.type = "button";
+      remove.className = "btn btn-sm btn-link d-print-none";
+      remove.textContent = recipes.dataset["remove"];
+      remove.addEventListener("click", (_) => {
+        store_list(load_list().filter((r) => r.link !== recipe.link));
+        show_list();
+      });
+      const li = document.createElement("li");
+      li.append(link);
+      if (recipe.servings !== null) li.append(` (${recipe.servings})`);
+      li.append(" ", remove);
+      return li;
+    })
+  );
+  empty.hidden = list.length > 0;
+  actions.hidden = list.length === 0;
+}
+
+function copy_list() {
+  const list = merge_items(load_list());
+  const text = list.map((item) => "- " + format_item(item)).join("\n");
+  navigator.clipboard.writeText(text);
+}
+
+window.addEventListener("DOMContentLoaded", (_) => {
+  // The shopping list requires JavaScript.
+  for (const elem of document.getElementsByClassName("shopping_link")) {
+    elem.hidden = false;
+  }
+  for (const elem of document.getElementsByClassName("shopping_add")) {
+    elem.hidden = false;
+    elem.addEventListener("click", (_) => add_recipe(elem));
+  }
+
+  if (document.getElementById("shopping_items") === null) return;
+  document
+    .getElementById("shopping_print")
+    .addEventListener("click", (_) => window.print());
+  document.getElementById("shopping_copy").addEventListener("click", copy_list);
+  document.getElementById("shopping_clear").addEventListener("click", (_) => {
+    store_list([]);
+    show_list();
+  });
+  show_list();
+});
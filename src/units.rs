@@ -0,0 +1,106 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Dimension {
+    Mass,
+    Volume,
+    /// Temperatures are converted but never scaled with the servings.
+    Temperature,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum System {
+    Metric,
+    Imperial,
+}
+
+/// A unit which can be converted into other units of the same dimension.
+#[derive(Serialize)]
+pub(crate) struct Unit {
+    #[serde(skip)]
+    pub(crate) symbol: &'static str,
+    /// Alternative spellings recognized in recipes, compared case-insensitively.
+    #[serde(skip)]
+    pub(crate) aliases: &'static [&'static str],
+    pub(crate) dimension: Dimension,
+    pub(crate) system: System,
+    /// Value of one unit in the base unit of the dimension (g, ml, or °C).
+    pub(crate) factor: f64,
+    /// Added after applying the factor when converting to the base unit.
+    pub(crate) offset: f64,
+    /// Whether conversions may produce this unit.
+    pub(crate) preferred: bool,
+}
+
+const fn unit(
+    symbol: &'static str,
+    dimension: Dimension,
+    system: System,
+    factor: f64,
+    preferred: bool,
+    aliases: &'static [&'static str],
+) -> Unit {
+    Unit {
+        symbol,
+        aliases,
+        dimension,
+        system,
+        factor,
+        offset: 0.,
+        preferred,
+    }
+}
+
+use Dimension::*;
+use System::*;
+
+#[rustfmt::skip]
+pub(crate) const UNITS: &[Unit] = &[
+    unit("mg", Mass, Metric, 0.001, false, &["milligram", "milligrams"]),
+    unit("g", Mass, Metric, 1., true, &["gram", "grams", "gr"]),
+    unit("kg", Mass, Metric, 1000., true, &["kilogram", "kilograms"]),
+    unit("oz", Mass, Imperial, 28.349523125, true, &["ounce", "ounces"]),
+    unit("lb", Mass, Imperial, 453.59237, true, &["lbs", "pound", "pounds"]),
+    unit("ml", Volume, Metric, 1., true, &["milliliter", "milliliters", "millilitre", "millilitres"]),
+    unit("cl", Volume, Metric, 10., false, &["centiliter", "centiliters", "centilitre", "centilitres"]),
+    unit("dl", Volume, Metric, 100., false, &["deciliter", "deciliters", "decilitre", "decilitres"]),
+    unit("l", Volume, Metric, 1000., true, &["liter", "liters", "litre", "litres"]),
+    unit("tsp", Volume, Imperial, 4.92892159375, true, &["teaspoon", "teaspoons"]),
+    unit("tbsp", Volume, Imperial, 14.78676478125, true, &["tablespoon", "tablespoons"]),
+    unit("fl oz", Volume, Imperial, 29.5735295625, false, &["fluid ounce", "fluid ounces"]),
+    unit("cup", Volume, Imperial, 236.5882365, true, &["cups"]),
+    unit("°C", Temperature, Metric, 1., true, &["celsius"]),
+    Unit {
+        symbol: "°F",
+        aliases: &["fahrenheit"],
+        dimension: Temperature,
+        system: Imperial,
+        factor: 5. / 9.,
+        offset: -32. * 5. / 9.,
+        preferred: true,
+    },
+];
+
+/// Find a unit by its symbol or one of its aliases.
+pub(crate) fn lookup(name: &str) -> Option<&'static Unit> {
+    UNITS.iter().find(|u| {
+        u.symbol.eq_ignore_ascii_case(name)
+            || u.aliases.iter().any(|a| a.eq_ignore_ascii_case(name))
+    })
+}
+
+/// Script defining the unit table for `rumtopf.js`.
+pub(crate) fn units_js() -> String {
+    let units = UNITS
+        .iter()
+        .map(|u| (u.symbol, u))
+        .collect::<BTreeMap<_, _>>();
+    format!(
+        "\"use strict\";\n\nconst UNITS = {};\n",
+        serde_json::to_string(&units).expect("failed to serialize unit table")
+    )
+}
@@ -1,38 +1,121 @@
-use std::{collections::HashSet, fmt::Display, path::PathBuf};
+use std::{
+    collections::HashSet,
+    hash::Hash,
+    path::PathBuf,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use anyhow::{Context, Result};
 use handlebars::Handlebars;
 use serde::Serialize;
 
-use crate::Recipe;
+use crate::{
+    diagnostics::{Diagnostic, Diagnostics},
+    manifest::{hash, Manifest},
+    output::Output,
+    Recipe,
+};
 
 pub(crate) struct Ctx<'l> {
     pub(crate) src: PathBuf,
-    pub(crate) reg: Handlebars<'l>,
-    pub(crate) dest: PathBuf,
-    pub(crate) any_error: bool,
+    pub(crate) reg: &'l Handlebars<'static>,
+    /// Sink of the generated files, which is [`None`] when only checking the
+    /// sources.
+    pub(crate) output: Option<&'l dyn Output>,
+    pub(crate) diagnostics: Arc<Diagnostics>,
     pub(crate) title: Option<String>,
     pub(crate) links: Vec<Link>,
     pub(crate) footer: String,
+    pub(crate) prerender: Vec<f32>,
+    /// URL of the website ending in `/` for absolute links in feeds.
+    pub(crate) base_url: Option<String>,
+    /// Manifest of the previous generation for incremental generation.
+    pub(crate) previous: Option<Manifest>,
+    /// Manifest of the files generated so far.
+    pub(crate) manifest: Mutex<Manifest>,
+    /// Absolute URLs of the pages generated so far for the sitemap.
+    pub(crate) urls: Mutex<Vec<PageUrl>>,
 }
 
 impl Ctx<'_> {
-    pub(crate) fn print_error(&mut self, err: impl Display) {
-        self.any_error = true;
-        eprintln!("{err:#}");
+    /// Hash of the inputs of a file including the inputs shared by all files.
+    pub(crate) fn inputs(&self, inputs: &impl Hash) -> u64 {
+        hash(&(self.manifest().global, inputs))
+    }
+
+    /// Whether the file was generated from the same inputs before and still
+    /// exists.
+    pub(crate) fn is_fresh(&self, name: &str, inputs: u64) -> bool {
+        self.previous
+            .as_ref()
+            .and_then(|m| m.outputs.get(name))
+            .is_some_and(|&previous| previous == inputs)
+            && self.output.is_some_and(|o| o.exists(name))
+    }
+
+    /// Add the file to the manifest and return whether it was not yet added.
+    pub(crate) fn record(&self, name: &str, inputs: u64) -> bool {
+        self.manifest()
+            .outputs
+            .insert(name.to_string(), inputs)
+            .is_none()
+    }
+
+    pub(crate) fn forget(&self, name: &str) {
+        let mut manifest = self.manifest();
+        manifest.outputs.remove(name);
+        manifest.diagnostics.remove(name);
+    }
+
+    /// Diagnostics of rendering the file in the previous generation.
+    pub(crate) fn previous_diagnostics(&self, name: &str) -> Vec<Diagnostic> {
+        self.previous
+            .as_ref()
+            .and_then(|m| m.diagnostics.get(name))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Keep the diagnostics of rendering the file for the next generation.
+    pub(crate) fn record_diagnostics(&self, name: &str, diagnostics: Vec<Diagnostic>) {
+        if !diagnostics.is_empty() {
+            self.manifest()
+                .diagnostics
+                .insert(name.to_string(), diagnostics);
+        }
+    }
+
+    fn manifest(&self) -> MutexGuard<'_, Manifest> {
+        self.manifest.lock().unwrap_or_else(PoisonError::into_inner)
     }
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Hash, Serialize)]
 pub(crate) struct Link {
     pub(crate) label: String,
     pub(crate) href: String,
 }
 
+/// Absolute URL of a page and of its versions in other languages.
+#[derive(Serialize)]
+pub(crate) struct PageUrl {
+    pub(crate) canonical: String,
+    pub(crate) alternates: Vec<Alternate>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Alternate {
+    pub(crate) lang: String,
+    pub(crate) href: String,
+}
+
 pub(crate) struct Rtx<'r> {
     pub(crate) recipes: &'r [Recipe],
     pub(crate) default_lang: &'r str,
     pub(crate) langs: Vec<Option<&'r str>>,
+    /// All categories with recipes, including the root and intermediate ones.
+    pub(crate) categories: Vec<&'r [String]>,
 }
 
 impl<'r> Rtx<'r> {
@@ -44,10 +127,48 @@ impl<'r> Rtx<'r> {
             .into_iter()
             .collect::<Vec<_>>();
         langs.sort_unstable();
+        let mut categories = recipes
+            .iter()
+            .flat_map(|r| (0..=r.category.len()).map(|depth| &r.category[..depth]))
+            .collect::<Vec<_>>();
+        // The root index is also written without any recipes.
+        categories.push(&[]);
+        categories.sort_unstable();
+        categories.dedup();
         Self {
             recipes,
             default_lang,
             langs,
+            categories,
+        }
+    }
+}
+
+impl Rtx<'_> {
+    /// Index page to link from pages of a language.
+    pub(crate) fn index(&self, lang: Option<&str>) -> String {
+        if self.langs.len() < 2 {
+            "index.html".to_string()
+        } else {
+            page_for_lang("index", lang)
+        }
+    }
+
+    /// Shopping list page of a language, named like the index.
+    pub(crate) fn shopping(&self, lang: Option<&str>) -> String {
+        if self.langs.len() < 2 {
+            "shopping.html".to_string()
+        } else {
+            page_for_lang("shopping", lang)
+        }
+    }
+
+    /// Feed of the recipes of a language, named like the index.
+    pub(crate) fn feed(&self, lang: Option<&str>) -> String {
+        if self.langs.len() < 2 {
+            "feed.xml".to_string()
+        } else {
+            file_for_lang("feed", lang, "xml")
         }
     }
 }
@@ -59,3 +180,179 @@ where
     reg.render(name, data)
         .with_context(|| format!("failed to render template {name}"))
 }
+
+/// File name of a page which is generated once per language.
+pub(crate) fn page_for_lang(name: &str, lang: Option<&str>) -> String {
+    file_for_lang(name, lang, "html")
+}
+
+/// File name of a file which is generated once per language.
+pub(crate) fn file_for_lang(name: &str, lang: Option<&str>, extension: &str) -> String {
+    let mut file = name.to_string();
+    if let Some(lang) = lang {
+        file += ".";
+        file += lang;
+    }
+    file += ".";
+    file += extension;
+    file
+}
+
+/// Search index used by the pages of a language.
+pub(crate) fn search_index(lang: Option<&str>) -> String {
+    file_for_lang("search-index", lang, "json")
+}
+
+/// Page listing all recipes with the given tag.
+///
+/// Unlike the index, tag pages are always named after the language of the
+/// recipes so that links to them can be generated while parsing.
+pub(crate) fn tag_page(tag: &str, lang: Option<&str>) -> String {
+    page_for_lang(&format!("tag.{}", slugify(tag)), lang)
+}
+
+/// Relative path from pages of a category to the destination directory.
+pub(crate) fn root_for(category: &[String]) -> String {
+    "../".repeat(category.len())
+}
+
+/// Path of a page of a category relative to the destination directory.
+pub(crate) fn category_page(category: &[String], name: &str) -> String {
+    let mut page = String::new();
+    for dir in category {
+        page += dir;
+        page += "/";
+    }
+    page + name
+}
+
+/// Recipe page pre-rendered for another number of servings.
+pub(crate) fn servings_page(stem: &str, servings: f32) -> String {
+    format!("{stem}.servings-{servings}.html")
+}
+
+/// Format a time in UTC like `2024-05-01T12:30:00Z`.
+pub(crate) fn rfc3339(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    format_utc(seconds)
+}
+
+/// Format seconds since 1970-01-01 in UTC like [`rfc3339`].
+pub(crate) fn format_utc(seconds: i64) -> String {
+    let (days, seconds) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+
+    // Convert days since 1970-01-01 to a civil date, see
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Days since 1970-01-01 of a civil date, see
+/// https://howardhinnant.github.io/date_algorithms.html#days_from_civil
+pub(crate) fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+pub(crate) fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Escape text for XML content and attribute values.
+pub(crate) fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Decode `%XX` escapes of a URL path, like in request targets and links.
+pub fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Turn arbitrary text into a lowercase string usable as a file stem.
+pub(crate) fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('_') {
+            slug.push('_');
+        }
+    }
+    slug.truncate(slug.trim_end_matches('_').len());
+    slug
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn rfc3339_in_utc() {
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_217_000);
+        assert_eq!(rfc3339(time), "2024-02-29T14:30:00Z");
+        assert_eq!(format_utc(-1), "1969-12-31T23:59:59Z");
+    }
+
+    #[test]
+    fn civil_dates() {
+        for (year, month, day) in [(1970, 1, 1), (2000, 2, 29), (2024, 12, 31), (1900, 3, 1)] {
+            let seconds = days_from_civil(year, month, day) * 86400;
+            let expected = format!("{year:04}-{month:02}-{day:02}T00:00:00Z");
+            assert_eq!(format_utc(seconds), expected);
+        }
+        assert_eq!(days_in_month(2024, 2), 29);
+        assert_eq!(days_in_month(1900, 2), 28);
+        assert_eq!(days_in_month(2000, 2), 29);
+        assert_eq!(days_in_month(2023, 4), 30);
+    }
+}
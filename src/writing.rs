@@ -1,10 +1,20 @@
-use std::{collections::HashSet, fs::File, io::Write};
+use std::{
+    collections::{BTreeMap, HashSet},
+    hash::Hash,
+    mem::take,
+    sync::PoisonError,
+};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use rayon::prelude::*;
 use serde::Serialize;
-use serde_json::json;
+use serde_json::{json, Map, Value};
 
-use crate::{render, Ctx, Recipe, Rtx};
+use crate::{
+    category_page, escape_xml, feed::feed_link, meta::Yield, page_for_lang, render,
+    render_servings, root_for, search_index, servings_page, slugify, tag_page, units::lookup,
+    Alternate, Ctx, Link, PageUrl, Recipe, Rtx,
+};
 
 #[derive(Clone, Serialize)]
 struct LangPage<'r> {
@@ -12,112 +22,213 @@ struct LangPage<'r> {
     link: String,
 }
 
-pub(crate) fn write_recipes(ctx: &mut Ctx, rtx: &Rtx) {
-    for recipe in rtx.recipes {
+#[derive(Serialize)]
+struct TagLink<'r> {
+    name: &'r str,
+    link: String,
+}
+
+/// Link to a category in the breadcrumbs or the index.
+#[derive(Serialize)]
+struct CategoryLink<'r> {
+    name: &'r str,
+    link: Option<String>,
+    count: usize,
+}
+
+#[derive(Serialize)]
+struct SearchEntry<'r> {
+    title: &'r str,
+    link: String,
+    tags: &'r [String],
+    text: &'r str,
+}
+
+/// Recipes of one tag, keyed by the slug of the tag.
+type Tags<'r> = BTreeMap<String, (&'r str, Vec<&'r Recipe>)>;
+
+pub(crate) fn write_recipes(ctx: &Ctx, rtx: &Rtx) {
+    rtx.recipes.par_iter().for_each(|recipe| {
         let langs = rtx
             .recipes
             .iter()
-            .filter(|r| r.short == recipe.short && r.lang != recipe.lang)
+            .filter(|r| {
+                r.short == recipe.short && r.category == recipe.category && r.lang != recipe.lang
+            })
             .map(|r| LangPage {
                 lang: r.lang.as_deref(),
                 link: r.stem.to_string() + ".html",
             })
             .collect::<Vec<_>>();
-        let index = if rtx.langs.len() < 2 {
-            "index.html".to_string()
-        } else {
-            index_for_lang(recipe.lang.as_deref())
-        };
-        if let Err(err) = write_recipe(ctx, recipe, rtx.default_lang, &langs, &index)
+        if let Err(err) = write_recipe(ctx, rtx, recipe, &langs)
             .with_context(|| format!("Skipping writing recipe {}", recipe.title))
         {
-            ctx.print_error(err);
+            ctx.diagnostics.error(err);
         }
-    }
+    });
 }
 
-fn write_recipe(
-    ctx: &Ctx,
-    recipe: &Recipe,
-    default_lang: &str,
-    langs: &[LangPage],
-    index: &str,
-) -> Result<()> {
-    let html = render(
-        &ctx.reg,
-        "recipe",
-        &json!({
-            "recipe": recipe.recipe,
+fn write_recipe(ctx: &Ctx, rtx: &Rtx, recipe: &Recipe, langs: &[LangPage]) -> Result<()> {
+    let lang = recipe.lang.as_deref();
+    let root = root_for(&recipe.category);
+    let tags = recipe
+        .tags
+        .iter()
+        .map(|t| TagLink {
+            name: t,
+            link: root.clone() + &tag_page(t, lang),
+        })
+        .collect::<Vec<_>>();
+    let mut breadcrumbs = breadcrumbs(rtx, &recipe.category, lang, &root);
+    if !breadcrumbs.is_empty() {
+        breadcrumbs.push(CategoryLink {
+            name: &recipe.title,
+            link: None,
+            count: 0,
+        });
+    }
+    let prerendered = recipe.servings.is_some() && !ctx.prerender.is_empty();
+    let json_ld = schema_recipe(recipe, lang.unwrap_or(rtx.default_lang));
+    let url = page_url(ctx, &recipe.link, lang, langs);
+    let shopping_ingredients =
+        (!recipe.ingredients.is_empty()).then(|| shopping_ingredients(recipe));
+    let data = |html: &str| {
+        json!({
+            "recipe": html,
             "title": recipe.title,
-            "ctx": template_ctx(ctx),
-            "index": index,
-            "lang": recipe.lang.as_deref().unwrap_or(default_lang),
+            "link": recipe.link,
+            "meta": recipe.meta,
+            "ingredients": recipe.ingredients,
+            "ctx": template_ctx(ctx, &root),
+            "root": root,
+            "index": rtx.index(lang),
+            "lang": lang.unwrap_or(rtx.default_lang),
             "langs": langs,
-        }),
-    )?;
+            "breadcrumbs": breadcrumbs,
+            "tags": tags,
+            "search": search_index(lang),
+            "feed": feed_link(ctx, rtx, lang),
+            "prerendered": prerendered,
+            "json_ld": json_ld,
+            "url": url,
+            "shopping": rtx.shopping(lang),
+            "shopping_ingredients": shopping_ingredients,
+        })
+    };
+    let page = data(&recipe.recipe);
 
     // short was a valid file stem so it should be safe to use as a stem here
     // too.
-    let path = ctx.dest.join(recipe.stem.to_string() + ".html");
-    let mut file = File::options()
-        .write(true)
-        .create_new(true)
-        .open(&path)
-        .with_context(|| format!("failed to create HTML file {}", path.to_string_lossy()))?;
-    file.write_all(html.as_bytes())
-        .with_context(|| format!("failed to write HTML file {}", path.to_string_lossy()))?;
+    write_rendered(ctx, &recipe.link, &page.to_string(), || {
+        render(ctx.reg, "recipe", &page)
+    })?;
 
+    if let Some(base) = recipe.servings {
+        for &servings in ctx.prerender.iter().filter(|&&s| s != base) {
+            let name = category_page(&recipe.category, &servings_page(&recipe.stem, servings));
+            // Avoid rendering the recipe again just for comparing the inputs.
+            let inputs = (data("").to_string(), &recipe.source, servings.to_bits());
+            write_rendered(ctx, &name, &inputs, || {
+                let html = render_servings(ctx, recipe, servings);
+                render(ctx.reg, "recipe", &data(&html))
+            })?;
+        }
+    }
     Ok(())
 }
 
-pub(crate) fn write_indices(ctx: &mut Ctx<'_>, rtx: &Rtx) {
+/// Links to the index pages of the category and all its parents.
+fn breadcrumbs<'r>(
+    rtx: &Rtx,
+    category: &'r [String],
+    lang: Option<&str>,
+    root: &str,
+) -> Vec<CategoryLink<'r>> {
+    (1..=category.len())
+        .map(|depth| CategoryLink {
+            name: &category[depth - 1],
+            link: Some(root.to_string() + &category_page(&category[..depth], &rtx.index(lang))),
+            count: 0,
+        })
+        .collect()
+}
+
+/// Write the index pages of every category.
+///
+/// The destination directory itself is the root category.
+pub(crate) fn write_indices(ctx: &Ctx<'_>, rtx: &Rtx) {
+    for category in rtx.categories.iter() {
+        write_category_indices(ctx, rtx, category);
+    }
+}
+
+fn write_category_indices(ctx: &Ctx<'_>, rtx: &Rtx, category: &[String]) {
+    let recipes = recipes_in(rtx, category);
     if rtx.langs.len() < 2 {
         let lang = rtx.langs.iter().cloned().next().flatten();
-        if let Err(err) = create_index(ctx, rtx.recipes, lang, Default::default(), false) {
-            ctx.print_error(err);
+        if let Err(err) = create_index(ctx, rtx, category, &recipes, lang, &[]) {
+            ctx.diagnostics.error(err);
         }
-    } else {
-        let langs = rtx
-            .langs
+        return;
+    }
+
+    let mut langs = langs_of(&recipes);
+    if category.is_empty() {
+        // The index of the root category is the language selection.
+        langs.retain(Option::is_some);
+    }
+    let langs = langs
+        .into_iter()
+        .map(|l| LangPage {
+            lang: l,
+            link: rtx.index(l),
+        })
+        .collect::<Vec<_>>();
+    for page in langs.iter() {
+        let langs = langs
             .iter()
-            .filter_map(|&l| l)
-            .map(|l| {
-                (
-                    l,
-                    LangPage {
-                        lang: Some(l),
-                        link: index_for_lang(Some(l)),
-                    },
-                )
-            })
+            .filter(|l| l.lang != page.lang)
             .collect::<Vec<_>>();
-        for &(lang, _) in langs.iter() {
-            let langs = langs
-                .iter()
-                .map(|(_, lang)| lang)
-                .filter(|l| l.lang != Some(lang))
-                .collect::<Vec<_>>();
-            if let Err(err) = create_index(ctx, rtx.recipes, Some(lang), &langs, true) {
-                ctx.print_error(err);
-            }
+        if let Err(err) = create_index(ctx, rtx, category, &recipes, page.lang, &langs) {
+            ctx.diagnostics.error(err);
         }
-        let langs = langs.into_iter().map(|(_, lang)| lang).collect::<Vec<_>>();
+    }
+    if category.is_empty() {
         if let Err(err) = write_lang_select(ctx, rtx.recipes, &langs) {
-            ctx.print_error(err);
+            ctx.diagnostics.error(err);
         }
     }
 }
 
+/// Recipes of the category including its subcategories.
+fn recipes_in<'r>(rtx: &Rtx<'r>, category: &[String]) -> Vec<&'r Recipe> {
+    rtx.recipes
+        .iter()
+        .filter(|r| r.category.starts_with(category))
+        .collect()
+}
+
+fn langs_of<'r>(recipes: &[&'r Recipe]) -> Vec<Option<&'r str>> {
+    let mut langs = recipes
+        .iter()
+        .map(|r| r.lang.as_deref())
+        .collect::<Vec<_>>();
+    langs.sort_unstable();
+    langs.dedup();
+    langs
+}
+
 fn create_index(
     ctx: &Ctx,
-    recipes: &[Recipe],
+    rtx: &Rtx,
+    category: &[String],
+    recipes: &[&Recipe],
     lang: Option<&str>,
     langs: &[&LangPage],
-    localized: bool,
 ) -> Result<()> {
     let mut this_lang = Vec::new();
     let mut other_lang = Vec::new();
-    for recipe in recipes {
+    for &recipe in recipes {
         if recipe.lang.as_deref() == lang {
             this_lang.push(recipe);
         } else {
@@ -125,78 +236,522 @@ fn create_index(
         }
     }
 
-    let name = index_for_lang(lang.filter(|_| localized));
-    let mut file = File::options()
-        .write(true)
-        .create_new(true)
-        .open(ctx.dest.join(&name))
-        .with_context(|| format!("failed to create {name} file"))?;
-
-    file.write_all(
-        render(
-            &ctx.reg,
-            "index",
-            &json!({
-                "ctx": template_ctx(ctx),
-                "this_lang": &this_lang,
-                "other_lang": &other_lang,
-                "index": "index.html",
-                "langs": langs,
-                "lang": lang,
-            }),
-        )?
-        .as_bytes(),
-    )
-    .context("failed to write index.html file")?;
+    let tags_page = this_lang
+        .iter()
+        .any(|r| !r.tags.is_empty())
+        .then(|| page_for_lang("tags", lang));
 
-    Ok(())
+    let categories = rtx
+        .categories
+        .iter()
+        .filter(|c| c.len() == category.len() + 1 && c.starts_with(category))
+        .map(|c| {
+            let recipes = recipes_in(rtx, c);
+            let langs = langs_of(&recipes);
+            // Not every subcategory has recipes in this language.
+            let index_lang = if langs.contains(&lang) {
+                lang
+            } else {
+                langs.first().copied().flatten()
+            };
+            CategoryLink {
+                name: &c[category.len()],
+                link: Some(category_page(&c[category.len()..], &rtx.index(index_lang))),
+                count: recipes.len(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let root = root_for(category);
+    let mut breadcrumbs = breadcrumbs(rtx, category, lang, &root);
+    if let Some(current) = breadcrumbs.last_mut() {
+        current.link = None;
+    }
+
+    // The root index pages link to the language selection.
+    let (name, index) = if category.is_empty() {
+        let localized = rtx.langs.len() >= 2;
+        let name = page_for_lang("index", lang.filter(|_| localized));
+        (name, "index.html".to_string())
+    } else {
+        (category_page(category, &rtx.index(lang)), rtx.index(lang))
+    };
+    let url = page_url(ctx, &name, lang, langs.iter().copied());
+    let data = json!({
+        "ctx": template_ctx(ctx, &root),
+        "root": root,
+        "category": category.last(),
+        "categories": categories,
+        "this_lang": &this_lang,
+        "other_lang": &other_lang,
+        "index": index,
+        "langs": langs,
+        "lang": lang,
+        "breadcrumbs": breadcrumbs,
+        "tags_page": tags_page,
+        "search": search_index(lang),
+        "feed": feed_link(ctx, rtx, lang),
+        "shopping": rtx.shopping(lang),
+        "url": url,
+    });
+    write_rendered(ctx, &name, &data.to_string(), || {
+        render(ctx.reg, "index", &data)
+    })
 }
 
-fn write_lang_select(ctx: &mut Ctx<'_>, recipes: &[Recipe], langs: &[LangPage]) -> Result<()> {
+fn write_lang_select(ctx: &Ctx<'_>, recipes: &[Recipe], langs: &[LangPage]) -> Result<()> {
     let recipe_count = recipes
         .iter()
-        .map(|r| &r.short)
+        .map(|r| (&r.category, &r.short))
         .collect::<HashSet<_>>()
         .len();
 
-    let mut file = File::options()
-        .write(true)
-        .create_new(true)
-        .open(ctx.dest.join("index.html"))
-        .context("failed to create index.html")?;
-
-    file.write_all(
-        render(
-            &ctx.reg,
-            "lang",
-            &json!({
-                "ctx": template_ctx(ctx),
-                "recipes": recipes,
-                "langs": langs,
-                "index": "index.html",
-                "recipe_count": recipe_count,
-            }),
-        )?
-        .as_bytes(),
-    )
-    .context("failed to write index.html file")?;
+    let data = json!({
+        "ctx": template_ctx(ctx, ""),
+        "root": "",
+        "recipes": recipes,
+        "langs": langs,
+        "index": "index.html",
+        "breadcrumbs": [],
+        "recipe_count": recipe_count,
+        "search": null,
+        "feed": null,
+        "shopping": null,
+        "url": page_url(ctx, "index.html", None, []),
+    });
+    write_rendered(ctx, "index.html", &data.to_string(), || {
+        render(ctx.reg, "lang", &data)
+    })
+}
+
+pub(crate) fn write_tags(ctx: &Ctx<'_>, rtx: &Rtx) {
+    let tags = rtx
+        .langs
+        .iter()
+        .map(|&lang| (lang, tags_for_lang(rtx.recipes, lang)))
+        .collect::<Vec<_>>();
+
+    for (lang, lang_tags) in tags.iter() {
+        if lang_tags.is_empty() {
+            continue;
+        }
+
+        for (slug, (name, this_lang)) in lang_tags {
+            let other_lang = tags
+                .iter()
+                .filter(|(l, _)| l != lang)
+                .filter_map(|(_, t)| t.get(slug))
+                .flat_map(|(_, recipes)| recipes.iter().copied())
+                .collect::<Vec<_>>();
+            let langs = tags
+                .iter()
+                .filter(|(l, t)| l != lang && t.contains_key(slug))
+                .map(|(l, _)| LangPage {
+                    lang: *l,
+                    link: tag_page(slug, *l),
+                })
+                .collect::<Vec<_>>();
+            if let Err(err) = write_tag(ctx, rtx, *lang, name, this_lang, &other_lang, &langs)
+                .with_context(|| format!("Skipping writing tag {name}"))
+            {
+                ctx.diagnostics.error(err);
+            }
+        }
+
+        let langs = tags
+            .iter()
+            .filter(|(l, t)| l != lang && !t.is_empty())
+            .map(|(l, _)| LangPage {
+                lang: *l,
+                link: page_for_lang("tags", *l),
+            })
+            .collect::<Vec<_>>();
+        if let Err(err) = write_tag_overview(ctx, rtx, *lang, lang_tags, &langs) {
+            ctx.diagnostics.error(err);
+        }
+    }
+}
+
+fn tags_for_lang<'r>(recipes: &'r [Recipe], lang: Option<&str>) -> Tags<'r> {
+    let mut tags = Tags::new();
+    for recipe in recipes.iter().filter(|r| r.lang.as_deref() == lang) {
+        for tag in recipe.tags.iter() {
+            tags.entry(slugify(tag))
+                .or_insert_with(|| (tag, Vec::new()))
+                .1
+                .push(recipe);
+        }
+    }
+    tags
+}
+
+fn write_tag(
+    ctx: &Ctx,
+    rtx: &Rtx,
+    lang: Option<&str>,
+    tag: &str,
+    this_lang: &[&Recipe],
+    other_lang: &[&Recipe],
+    langs: &[LangPage],
+) -> Result<()> {
+    let name = tag_page(tag, lang);
+    let data = json!({
+        "ctx": template_ctx(ctx, ""),
+        "root": "",
+        "tag": tag,
+        "this_lang": this_lang,
+        "other_lang": other_lang,
+        "index": rtx.index(lang),
+        "langs": langs,
+        "lang": lang,
+        "breadcrumbs": [],
+        "tags_page": page_for_lang("tags", lang),
+        "search": search_index(lang),
+        "feed": feed_link(ctx, rtx, lang),
+        "shopping": rtx.shopping(lang),
+        "url": page_url(ctx, &name, lang, langs),
+    });
+    write_rendered(ctx, &name, &data.to_string(), || {
+        render(ctx.reg, "tag", &data)
+    })
+}
+
+fn write_tag_overview(
+    ctx: &Ctx,
+    rtx: &Rtx,
+    lang: Option<&str>,
+    tags: &Tags,
+    langs: &[LangPage],
+) -> Result<()> {
+    let tags = tags
+        .values()
+        .map(|(name, recipes)| {
+            json!({
+                "name": name,
+                "link": tag_page(name, lang),
+                "count": recipes.len(),
+            })
+        })
+        .collect::<Vec<_>>();
+    let name = page_for_lang("tags", lang);
+    let data = json!({
+        "ctx": template_ctx(ctx, ""),
+        "root": "",
+        "tags": tags,
+        "index": rtx.index(lang),
+        "langs": langs,
+        "lang": lang,
+        "breadcrumbs": [],
+        "search": search_index(lang),
+        "feed": feed_link(ctx, rtx, lang),
+        "shopping": rtx.shopping(lang),
+        "url": page_url(ctx, &name, lang, langs),
+    });
+    write_rendered(ctx, &name, &data.to_string(), || {
+        render(ctx.reg, "tags", &data)
+    })
+}
 
+/// Write one shopping list page per language.
+///
+/// The recipes on the list are stored in the browser, so the pages are the
+/// same for all recipes.
+pub(crate) fn write_shopping_lists(ctx: &Ctx<'_>, rtx: &Rtx) {
+    for &lang in rtx.langs.iter() {
+        let langs = rtx
+            .langs
+            .iter()
+            .filter(|&&l| l != lang)
+            .map(|&l| LangPage {
+                lang: l,
+                link: rtx.shopping(l),
+            })
+            .collect::<Vec<_>>();
+        let name = rtx.shopping(lang);
+        let url = page_url(ctx, &name, lang, &langs);
+        let data = json!({
+            "ctx": template_ctx(ctx, ""),
+            "root": "",
+            "index": rtx.index(lang),
+            "langs": langs,
+            "lang": lang.unwrap_or(rtx.default_lang),
+            "breadcrumbs": [],
+            "search": search_index(lang),
+            "feed": feed_link(ctx, rtx, lang),
+            "shopping": name,
+            "url": url,
+        });
+        if let Err(err) = write_rendered(ctx, &name, &data.to_string(), || {
+            render(ctx.reg, "shopping", &data)
+        }) {
+            ctx.diagnostics.error(err);
+        }
+    }
+}
+
+/// Ingredients of a recipe for adding them to the shopping list in the
+/// browser, which scales them like the quantities of the page.
+fn shopping_ingredients(recipe: &Recipe) -> String {
+    let ingredients = recipe
+        .ingredients
+        .iter()
+        .map(|ingredient| {
+            let quantity = ingredient.quantity.as_ref().map(|quantity| {
+                // Keep the shortest representation of the amounts like on the
+                // page.
+                let number = |n: f32| n.to_string().parse::<f64>().ok();
+                json!({
+                    "amount": number(quantity.amount),
+                    "max": quantity.max.and_then(number),
+                    "fraction": quantity.fraction,
+                    "unit": quantity.unit,
+                    "symbol": quantity.unit.as_deref().and_then(lookup).map(|u| u.symbol),
+                    "scaling": quantity.scaling,
+                })
+            });
+            json!({
+                "name": ingredient.name,
+                "note": ingredient.note,
+                "quantity": quantity,
+            })
+        })
+        .collect();
+
+    // Prevent closing the surrounding script element early.
+    Value::Array(ingredients).to_string().replace("</", r"<\/")
+}
+
+/// Write one search index per language.
+///
+/// Recipes without a language are included in every index.
+pub(crate) fn write_search_indices(ctx: &Ctx<'_>, rtx: &Rtx) {
+    for &lang in rtx.langs.iter() {
+        let entries = rtx
+            .recipes
+            .iter()
+            .filter(|r| r.lang.is_none() || r.lang.as_deref() == lang)
+            .map(|r| SearchEntry {
+                title: &r.title,
+                link: r.link.clone(),
+                tags: &r.tags,
+                text: &r.text,
+            })
+            .collect::<Vec<_>>();
+        let name = search_index(lang);
+        if let Err(err) = serde_json::to_string(&entries)
+            .context("failed to serialize search index")
+            .and_then(|json| write_rendered(ctx, &name, &json, || Ok(json.clone())))
+            .with_context(|| format!("Skipping writing search index {name}"))
+        {
+            ctx.diagnostics.error(err);
+        }
+    }
+}
+
+/// Absolute URLs of a page and of its versions in other languages, which are
+/// also added to the sitemap.
+///
+/// Links of the other languages are relative to the directory of the page.
+/// Versions without a language are the default for all other languages.
+/// Without a base URL, there are no absolute URLs.
+fn page_url<'p>(
+    ctx: &Ctx,
+    name: &str,
+    lang: Option<&str>,
+    langs: impl IntoIterator<Item = &'p LangPage<'p>>,
+) -> Value {
+    let Some(base) = &ctx.base_url else {
+        return Value::Null;
+    };
+    let dir = &name[..name.rfind('/').map_or(0, |i| i + 1)];
+    let mut alternates = langs
+        .into_iter()
+        .map(|l| Alternate {
+            lang: l.lang.unwrap_or("x-default").to_string(),
+            href: format!("{base}{dir}{}", l.link),
+        })
+        .collect::<Vec<_>>();
+    let canonical = format!("{base}{name}");
+    if !alternates.is_empty() {
+        // The alternates of every version include the version itself.
+        alternates.push(Alternate {
+            lang: lang.unwrap_or("x-default").to_string(),
+            href: canonical.clone(),
+        });
+        alternates.sort_unstable_by(|a, b| a.lang.cmp(&b.lang));
+    }
+
+    let url = PageUrl {
+        canonical,
+        alternates,
+    };
+    let value = json!(url);
+    ctx.urls
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(url);
+    value
+}
+
+/// Write a sitemap of all pages with their versions in other languages.
+pub(crate) fn write_sitemap(ctx: &Ctx) {
+    if ctx.base_url.is_none() {
+        return;
+    }
+    let mut urls = take(&mut *ctx.urls.lock().unwrap_or_else(PoisonError::into_inner));
+    urls.sort_unstable_by(|a, b| a.canonical.cmp(&b.canonical));
+
+    let mut xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9" xmlns:xhtml="http://www.w3.org/1999/xhtml">
+"#
+    .to_string();
+    for url in urls {
+        xml += &format!("<url>\n<loc>{}</loc>\n", escape_xml(&url.canonical));
+        for alternate in url.alternates {
+            xml += &format!(
+                "<xhtml:link rel=\"alternate\" hreflang=\"{}\" href=\"{}\"/>\n",
+                escape_xml(&alternate.lang),
+                escape_xml(&alternate.href)
+            );
+        }
+        xml += "</url>\n";
+    }
+    xml += "</urlset>\n";
+
+    if let Err(err) = write_rendered(ctx, "sitemap.xml", &xml, || Ok(xml.clone())) {
+        ctx.diagnostics
+            .error(err.context("Skipping writing sitemap"));
+    }
+}
+
+/// Render and write a page unless it was generated from the same inputs
+/// before.
+///
+/// The diagnostics of rendering a page which is not rendered again are
+/// reported from the previous generation.
+pub(crate) fn write_rendered(
+    ctx: &Ctx,
+    name: &str,
+    inputs: &impl Hash,
+    render: impl FnOnce() -> Result<String>,
+) -> Result<()> {
+    let inputs = ctx.inputs(inputs);
+    if !ctx.record(name, inputs) {
+        bail!("file {name} was already generated");
+    }
+    let diagnostics = if ctx.is_fresh(name, inputs) {
+        let diagnostics = ctx.previous_diagnostics(name);
+        for diagnostic in &diagnostics {
+            ctx.diagnostics.push(diagnostic.clone());
+        }
+        diagnostics
+    } else {
+        let (result, diagnostics) = ctx.diagnostics.capture(render);
+        if let Err(err) = result.and_then(|content| write_page(ctx, name, &content)) {
+            ctx.forget(name);
+            return Err(err);
+        }
+        diagnostics
+    };
+    ctx.record_diagnostics(name, diagnostics);
     Ok(())
 }
 
-fn index_for_lang(lang: Option<&str>) -> String {
-    let mut name = "index".to_string();
-    if let Some(lang) = lang {
-        name += ".";
-        name += lang;
+fn write_page(ctx: &Ctx, name: &str, content: &str) -> Result<()> {
+    match ctx.output {
+        Some(output) => output.write(name, content.as_bytes()),
+        None => Ok(()),
+    }
+}
+
+/// Schema.org Recipe object for embedding as JSON-LD.
+fn schema_recipe(recipe: &Recipe, lang: &str) -> String {
+    let mut schema = Map::new();
+    schema.insert("@context".into(), "https://schema.org".into());
+    schema.insert("@type".into(), "Recipe".into());
+    schema.insert("name".into(), recipe.title.as_str().into());
+    schema.insert("inLanguage".into(), lang.into());
+
+    let meta = &recipe.meta;
+    if let Some(description) = &meta.description {
+        schema.insert("description".into(), description.as_str().into());
+    }
+    if let Some(author) = &meta.author {
+        schema.insert("author".into(), json!({"@type": "Person", "name": author}));
+    }
+    if !recipe.tags.is_empty() {
+        schema.insert("keywords".into(), recipe.tags.join(", ").into());
+    }
+    if let Some(source) = &meta.source {
+        schema.insert("isBasedOn".into(), source.as_str().into());
+    }
+
+    let times = [
+        ("prepTime", meta.prep_time),
+        ("cookTime", meta.cook_time),
+        (
+            "totalTime",
+            meta.prep_time
+                .zip(meta.cook_time)
+                .map(|(prep, cook)| prep + cook),
+        ),
+    ];
+    for (key, minutes) in times {
+        if let Some(minutes) = minutes {
+            schema.insert(key.into(), format!("PT{minutes}M").into());
+        }
     }
-    name += ".html";
-    name
+
+    let yields = match (recipe.servings, &meta.yields) {
+        (Some(servings), _) => Some(servings.to_string()),
+        (None, Some(Yield::Count(count))) => Some(count.to_string()),
+        (None, Some(Yield::Text(text))) => Some(text.clone()),
+        (None, None) => None,
+    };
+    if let Some(yields) = yields {
+        schema.insert("recipeYield".into(), yields.into());
+    }
+
+    let ingredients = recipe.ingredients.iter().map(|i| i.to_string().into());
+    schema.insert(
+        "recipeIngredient".into(),
+        Value::Array(ingredients.collect()),
+    );
+    let steps = recipe
+        .steps
+        .iter()
+        .map(|step| json!({"@type": "HowToStep", "text": step}));
+    schema.insert("recipeInstructions".into(), Value::Array(steps.collect()));
+
+    // Prevent closing the surrounding script element early.
+    Value::Object(schema).to_string().replace("</", r"<\/")
 }
 
-fn template_ctx(ctx: &Ctx) -> serde_json::Value {
+/// Make links given relative to the destination directory relative to pages
+/// with the given root.
+fn relative_link(root: &str, href: &str) -> String {
+    let absolute = href.starts_with(['/', '#'])
+        || href
+            .split_once(':')
+            .is_some_and(|(scheme, _)| !scheme.contains('/'));
+    if absolute {
+        href.to_string()
+    } else {
+        root.to_string() + href
+    }
+}
+
+pub(crate) fn template_ctx(ctx: &Ctx, root: &str) -> serde_json::Value {
+    let links = ctx
+        .links
+        .iter()
+        .map(|l| Link {
+            label: l.label.clone(),
+            href: relative_link(root, &l.href),
+        })
+        .collect::<Vec<_>>();
     json!({
-        "links": ctx.links,
+        "links": links,
         "footer": ctx.footer,
         "title": ctx.title.as_deref().unwrap_or("Recipes"),
         "custom_title": ctx.title.is_some(),
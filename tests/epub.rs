@@ -0,0 +1,197 @@
+//! Validate the package of exported EPUBs without any external tools.
+
+use std::{
+    collections::BTreeMap,
+    fs::{create_dir_all, remove_dir_all, write},
+    io::{Cursor, Read},
+    path::PathBuf,
+};
+
+use quick_xml::{events::Event, Reader};
+use rumtopf::{Report, Selection, Site};
+use zip::{CompressionMethod, ZipArchive};
+
+/// Source directory with the given files, removed when dropped.
+struct Source(PathBuf);
+
+impl Source {
+    fn new(name: &str, files: &[(&str, &[u8])]) -> Self {
+        let dir = std::env::temp_dir().join(format!("rumtopf-test-{}-{name}", std::process::id()));
+        let _ = remove_dir_all(&dir);
+        for (path, content) in files {
+            let path = dir.join(path);
+            create_dir_all(path.parent().unwrap()).unwrap();
+            write(path, content).unwrap();
+        }
+        Self(dir)
+    }
+
+    fn epub(&self) -> (Epub, Report) {
+        let mut site = Site::builder(&self.0).title("Test").build().unwrap();
+        let (epub, report) = site.epub(&Selection::new()).unwrap();
+        (Epub::new(epub), report)
+    }
+}
+
+impl Drop for Source {
+    fn drop(&mut self) {
+        let _ = remove_dir_all(&self.0);
+    }
+}
+
+/// Unpacked EPUB after checking its container.
+struct Epub {
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+impl Epub {
+    fn new(epub: Vec<u8>) -> Self {
+        let mut archive = ZipArchive::new(Cursor::new(epub)).unwrap();
+        let mimetype = archive.by_index(0).unwrap();
+        assert_eq!(mimetype.name(), "mimetype");
+        assert_eq!(mimetype.compression(), CompressionMethod::Stored);
+        drop(mimetype);
+
+        let mut files = BTreeMap::new();
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index).unwrap();
+            let mut content = Vec::new();
+            file.read_to_end(&mut content).unwrap();
+            files.insert(file.name().to_string(), content);
+        }
+        assert_eq!(files["mimetype"], b"application/epub+zip");
+        assert!(files.contains_key("META-INF/container.xml"));
+
+        let epub = Self { files };
+        for (name, content) in &epub.files {
+            if name.ends_with(".xhtml") || name.ends_with(".xml") || name.ends_with(".opf") {
+                check_well_formed(name, content);
+            }
+        }
+        for href in epub.manifest() {
+            assert!(
+                epub.files.contains_key(&format!("OEBPS/{href}")),
+                "{href} is missing"
+            );
+        }
+        epub
+    }
+
+    fn text(&self, name: &str) -> &str {
+        std::str::from_utf8(&self.files[name]).unwrap()
+    }
+
+    /// Paths of the items in the package document.
+    fn manifest(&self) -> Vec<String> {
+        let mut reader = Reader::from_str(self.text("OEBPS/content.opf"));
+        let mut hrefs = Vec::new();
+        loop {
+            match reader.read_event().unwrap() {
+                Event::Empty(e) | Event::Start(e) if e.name().as_ref() == b"item" => {
+                    let href = e.try_get_attribute("href").unwrap().unwrap();
+                    hrefs.push(href.unescape_value().unwrap().into_owned());
+                }
+                Event::Eof => return hrefs,
+                _ => {}
+            }
+        }
+    }
+
+    fn chapters(&self) -> Vec<&str> {
+        self.files
+            .keys()
+            .filter(|name| name.starts_with("OEBPS/recipe-"))
+            .map(|name| self.text(name))
+            .collect()
+    }
+}
+
+fn check_well_formed(name: &str, content: &[u8]) {
+    let mut reader = Reader::from_reader(content);
+    let mut buf = Vec::new();
+    let mut depth = 0usize;
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(_)) => depth += 1,
+            Ok(Event::End(_)) => depth -= 1,
+            Ok(Event::GeneralRef(r)) => assert!(
+                matches!(&*r, b"lt" | b"gt" | b"amp" | b"quot" | b"apos") || r.is_char_ref(),
+                "{name} uses an undefined entity"
+            ),
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(err) => panic!("{name} is not well-formed: {err}"),
+        }
+        buf.clear();
+    }
+    assert_eq!(depth, 0, "{name} has unclosed elements");
+}
+
+fn messages(report: &Report) -> Vec<&str> {
+    report.diagnostics().iter().map(|d| d.message()).collect()
+}
+
+#[test]
+fn raw_html_is_normalized() {
+    let source = Source::new(
+        "normalized",
+        &[(
+            "bread.md",
+            b"# Bread\n\nLine<br>break <img src=\"https://example.com/a.png\" alt=\"a\">\n\n<p>a&nbsp;b</p>\n",
+        )],
+    );
+    let (epub, report) = source.epub();
+    let chapter = epub.chapters()[0];
+    assert!(chapter.contains("Line<br />break"), "{chapter}");
+    assert!(chapter.contains("a&#160;b"), "{chapter}");
+    assert!(!chapter.contains("<img"), "{chapter}");
+    assert_eq!(report.diagnostics().len(), 1);
+    assert!(messages(&report)[0].contains("is not a local file"));
+}
+
+#[test]
+fn malformed_html_is_escaped() {
+    let source = Source::new("escaped", &[("bread.md", b"# Bread\n\n<div>unclosed\n")]);
+    let (epub, report) = source.epub();
+    let chapter = epub.chapters()[0];
+    assert!(chapter.contains("&lt;div&gt;unclosed"), "{chapter}");
+    assert!(messages(&report)[0].starts_with("Escaping HTML of recipe"));
+}
+
+#[test]
+fn local_images_are_packaged() {
+    let source = Source::new(
+        "images",
+        &[
+            (
+                "baking/bread.md",
+                b"# Bread\n\n![crumb](crumb.png) ![again](/baking/crumb.png)\n",
+            ),
+            ("baking/crumb.png", b"png"),
+            (
+                "baking_bread.md",
+                b"# Other Bread\n\n<img src=\"sub%20dir/x.jpg\" alt=\"x\"> ![](nope.png)\n",
+            ),
+            ("sub dir/x.jpg", b"jpg"),
+        ],
+    );
+    let (epub, report) = source.epub();
+    assert_eq!(epub.chapters().len(), 2);
+    let mut images = epub
+        .manifest()
+        .into_iter()
+        .filter(|href| href.starts_with("images/"))
+        .map(|href| epub.files[&format!("OEBPS/{href}")].clone())
+        .collect::<Vec<_>>();
+    images.sort();
+    assert_eq!(images, [b"jpg".to_vec(), b"png".to_vec()]);
+    let opf = epub.text("OEBPS/content.opf");
+    assert!(opf.contains(r#"media-type="image/png""#), "{opf}");
+    assert!(opf.contains(r#"media-type="image/jpeg""#), "{opf}");
+    assert!(epub
+        .chapters()
+        .iter()
+        .all(|c| !c.contains("nope.png") && c.contains(r#"src="images/"#)));
+    assert_eq!(report.diagnostics().len(), 1);
+    assert!(messages(&report)[0].contains("nope.png"));
+}
@@ -0,0 +1,74 @@
+//! Check the generated pages and exports through the public API.
+
+use std::{
+    fs::{create_dir_all, remove_dir_all, write},
+    io::{Cursor, Read},
+    path::PathBuf,
+};
+
+use rumtopf::{Memory, Selection, Site};
+use zip::ZipArchive;
+
+/// Source directory with one recipe, removed when dropped.
+struct Source(PathBuf);
+
+impl Source {
+    fn new(name: &str, recipe: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("rumtopf-test-{}-{name}", std::process::id()));
+        let _ = remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+        write(dir.join("recipe.md"), recipe).unwrap();
+        Self(dir)
+    }
+
+    fn site(&self) -> Site {
+        Site::builder(&self.0).build().unwrap()
+    }
+}
+
+impl Drop for Source {
+    fn drop(&mut self) {
+        let _ = remove_dir_all(&self.0);
+    }
+}
+
+const CODE: &str = "first line\n  indented line\nlast line\n";
+
+#[test]
+fn code_blocks_are_not_indented() {
+    let source = Source::new(
+        "code",
+        &format!("# Code\n\n```\n{CODE}```\n\n    four spaces\n"),
+    );
+
+    let output = Memory::new();
+    source.site().render(&output).unwrap();
+    let page = String::from_utf8(output.into_files()["recipe.html"].clone()).unwrap();
+    assert!(
+        page.contains(&format!("<pre><code>{CODE}</code></pre>")),
+        "{page}"
+    );
+    assert!(
+        page.contains("<pre><code>four spaces\n</code></pre>"),
+        "{page}"
+    );
+
+    let (book, _) = source.site().html_book(&Selection::new()).unwrap();
+    assert!(
+        book.contains(&format!("<pre><code>{CODE}</code></pre>")),
+        "{book}"
+    );
+
+    let (epub, _) = source.site().epub(&Selection::new()).unwrap();
+    let mut archive = ZipArchive::new(Cursor::new(epub)).unwrap();
+    let mut chapter = String::new();
+    archive
+        .by_name("OEBPS/recipe-1-recipe.xhtml")
+        .unwrap()
+        .read_to_string(&mut chapter)
+        .unwrap();
+    assert!(
+        chapter.contains(&format!("<pre><code>{CODE}</code></pre>")),
+        "{chapter}"
+    );
+}